@@ -1,12 +1,28 @@
 use std::env;
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::path::Path;
-use std::sync::Mutex;
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fs, io, process};
 
 use oc_rs::device_bus::RPCBus;
-use oc_rs::rpc_device::{FileImportExportCard, RPCDeviceDescriptor, RedstoneInterface};
-use oc_rs::util::Side;
+use oc_rs::redstone::output;
+use oc_rs::redstone::plc::Plc;
+use oc_rs::redstone::recorder::{play, read_samples, record, Format, SampleWriter};
+use oc_rs::redstone::signals::{Signals, SignalsConfig};
+use oc_rs::rpc_device::file_import_export_card::{ImportOptions, ImportState};
+use oc_rs::rpc_device::sound_card::{Instrument, Note, Pitch, Volume};
+use oc_rs::rpc_device::{FileImportExportCard, RPCDeviceDescriptor, RedstoneInterface, SoundCard};
+use oc_rs::transfer::archive::{pack_dir, unpack, unpack_all, Compression};
+use oc_rs::transfer::checksum::{
+    export_manifest, import_manifest, HashingReader, HashingWriter, Manifest,
+};
+use oc_rs::transfer::destination::safe_file_name;
+use oc_rs::transfer::{AtomicFile, ExportWriter, ImportReader, OverwritePolicy};
+use oc_rs::util::{ResourceLocation, Side};
+use serde_json::Value;
 
 fn main() {
     let bus_raw = RPCBus::init("/dev/hvc0").unwrap();
@@ -18,6 +34,7 @@ fn main() {
     match args.get(1).unwrap_or(&String::new()).as_str() {
         "methods" => print_methods(bus),
         "devices" => print_devices(bus),
+        "bench" => bench(bus, &args[2..]),
         "device" => {
             if let Some(a) = args.get(2) {
                 match args.get(3).unwrap_or(&String::new()).as_str() {
@@ -35,15 +52,17 @@ fn main() {
                     &_ => match a.as_str() {
                         "redstone" => {
                             let device = bus.lock().unwrap().find("redstone").unwrap();
-                            let mut device = RedstoneInterface { device, bus };
-                            let side = match args.get(4).unwrap().as_str() {
-                                "up" => Side::up,
-                                "down" => Side::down,
-                                "north" => Side::north,
-                                "east" => Side::east,
-                                "south" => Side::south,
-                                "west" => Side::west,
-                                &_ => Side::north,
+                            let device = Arc::new(RedstoneInterface { device, bus });
+                            let side: Side = match args.get(4).map(|s| s.parse()) {
+                                Some(Ok(side)) => side,
+                                Some(Err(e)) => {
+                                    println!("{}", e);
+                                    return;
+                                }
+                                None => {
+                                    println!("missing side");
+                                    return;
+                                }
                             };
                             match args.get(3).unwrap_or(&String::new()).as_str() {
                                 "getIn" => device.get_redstone_input(side).unwrap(),
@@ -56,6 +75,35 @@ fn main() {
                                     device.set_redstone_output(side, 0).unwrap();
                                     0
                                 }
+                                "pulse" => {
+                                    let ms =
+                                        args.get(5).and_then(|a| a.parse().ok()).unwrap_or(500);
+                                    let level =
+                                        args.get(6).and_then(|a| a.parse().ok()).unwrap_or(15);
+                                    output::pulse(device, side, level, Duration::from_millis(ms))
+                                        .unwrap()
+                                        .wait()
+                                        .unwrap();
+                                    0
+                                }
+                                "clock" => {
+                                    let ms =
+                                        args.get(5).and_then(|a| a.parse().ok()).unwrap_or(1000);
+                                    let duty =
+                                        args.get(6).and_then(|a| a.parse().ok()).unwrap_or(0.5);
+                                    let clock = output::clock(
+                                        device,
+                                        side,
+                                        15,
+                                        Duration::from_millis(ms),
+                                        duty,
+                                    )
+                                    .unwrap();
+                                    println!("press enter to stop");
+                                    io::stdin().read_line(&mut String::new()).unwrap();
+                                    clock.stop().unwrap();
+                                    0
+                                }
                                 &_ => {
                                     println!("missing arguments");
                                     0
@@ -69,31 +117,203 @@ fn main() {
                 println!("missing device specifier");
             }
         }
+        "redstone" => redstone(bus, &args[2..]),
+        "signal" => signal(bus, &args[2..]),
+        "sound" => sound(bus, &args[2..]),
+        "plc" => {
+            let path = match args.get(2) {
+                Some(p) => Path::new(p),
+                None => {
+                    println!("missing plc program");
+                    return;
+                }
+            };
+            let mut plc = match Plc::load(&bus, path) {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            // Stop between two scans on ctrl-c or SIGTERM, so the outputs get switched off.
+            let running = Arc::new(AtomicBool::new(true));
+            let handler = {
+                let running = running.clone();
+                ctrlc::set_handler(move || running.store(false, Ordering::SeqCst))
+            };
+            if let Err(e) = handler {
+                eprintln!("could not install the signal handler: {}", e);
+                return;
+            }
+            if let Err(e) = plc.run(|_| running.load(Ordering::SeqCst)) {
+                eprintln!("plc stopped: {}", e);
+            }
+        }
         "import-file" => {
+            let verify = args.iter().any(|a| a == "--verify");
+            let policy = match args.iter().find_map(|a| a.strip_prefix("--overwrite=")) {
+                Some(p) => match p.parse() {
+                    Ok(p) => p,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
+                    }
+                },
+                None => OverwritePolicy::Refuse,
+            };
+            let out_path = args.iter().skip(2).find(|a| !a.starts_with("--"));
+
             let device = bus.lock().unwrap().find("file_import_export").unwrap();
             let mut device = FileImportExportCard { device, bus };
 
             device.reset().unwrap();
-            device.request_import_file().unwrap();
+            let session = match device.import(ImportOptions::default(), print_import_state) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+
+            // A path given on the command line is trusted, the name the player's file came with
+            // is not.
+            let out_path = match out_path {
+                Some(p) => PathBuf::from(p),
+                None => match safe_file_name(session.info().name()) {
+                    Ok(name) => Path::new(".").join(name),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
+                    }
+                },
+            };
+            let mut file = match AtomicFile::create(&out_path, policy) {
+                Ok(f) => f,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+
+            let mut reader = HashingReader::new(ImportReader::new(session));
+            io::copy(&mut reader, &mut file).unwrap();
+            let digest = reader.digest();
+            drop(reader);
+            println!("{}", digest);
+
+            if verify {
+                println!("pick the manifest to verify against");
+                let manifest = device
+                    .import(ImportOptions::default(), print_import_state)
+                    .and_then(import_manifest);
+                match manifest.and_then(|m| m.verify(&digest)) {
+                    Ok(()) => println!("verified"),
+                    Err(e) => {
+                        eprintln!("verification failed: {}", e);
+                        return;
+                    }
+                }
+            }
+
+            match file.commit() {
+                Ok(path) => println!("saved to {}", path.display()),
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+        "export-file" => {
+            let with_manifest = args.iter().any(|a| a == "--manifest");
+            let mut rest = args.iter().skip(2).filter(|a| *a != "--manifest");
+            let path = match rest.next() {
+                Some(p) => Path::new(p),
+                None => {
+                    println!("missing file to export");
+                    return;
+                }
+            };
+            let name = match rest.next() {
+                Some(n) => n.clone(),
+                None => path.file_name().unwrap().to_string_lossy().to_string(),
+            };
+            let mut file = File::open(path).unwrap();
+            let total = file.metadata().unwrap().len();
+
+            let device = bus.lock().unwrap().find("file_import_export").unwrap();
+            let mut device = FileImportExportCard { device, bus };
+            device.reset().unwrap();
+
+            let writer = ExportWriter::begin(&mut device, &name)
+                .unwrap()
+                .with_total(total)
+                .on_progress(print_progress);
+            let mut writer = HashingWriter::new(writer);
+            io::copy(&mut file, &mut writer).unwrap();
+            let (writer, digest) = writer.into_inner();
+            writer.finish().unwrap();
+            println!();
+            println!("{}", digest);
 
-            let info = device.begin_import_file().unwrap();
-            let out_name = if let Some(s) = args.get(2) {
-                s.clone()
+            if with_manifest {
+                export_manifest(&mut device, &Manifest::new(&name, &digest)).unwrap();
+            }
+        }
+        "export-dir" => {
+            let gzip = args.iter().any(|a| a == "--gzip");
+            let with_manifest = args.iter().any(|a| a == "--manifest");
+            let mut rest = args.iter().skip(2).filter(|a| !a.starts_with("--"));
+            let dir = match rest.next() {
+                Some(d) => Path::new(d),
+                None => {
+                    println!("missing directory to export");
+                    return;
+                }
+            };
+            let compression = if gzip {
+                Compression::Gzip
             } else {
-                info.get_name()
+                Compression::None
+            };
+            let name = match rest.next() {
+                Some(n) => n.clone(),
+                None => format!(
+                    "{}.{}",
+                    dir.canonicalize()
+                        .unwrap()
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy(),
+                    compression.extension()
+                ),
             };
 
-            if Path::new(format!("./{}", &out_name).as_str()).exists() {
-                eprintln!("That file already exists");
-                return;
+            let device = bus.lock().unwrap().find("file_import_export").unwrap();
+            let mut device = FileImportExportCard { device, bus };
+            device.reset().unwrap();
+
+            let writer = ExportWriter::begin(&mut device, &name)
+                .unwrap()
+                .on_progress(print_progress);
+            let writer = pack_dir(dir, HashingWriter::new(writer), compression).unwrap();
+            let (writer, digest) = writer.into_inner();
+            writer.finish().unwrap();
+            println!();
+            println!("{}", digest);
+
+            if with_manifest {
+                export_manifest(&mut device, &Manifest::new(&name, &digest)).unwrap();
             }
+        }
+        "import-dir" => {
+            let verify = args.iter().any(|a| a == "--verify");
+            let dest = args.iter().skip(2).find(|a| !a.starts_with("--"));
+            let dest = Path::new(dest.map(|d| d.as_str()).unwrap_or("."));
 
-            let data = device.read_import_file().unwrap();
-            let mut file = OpenOptions::new()
-                .write(true)
-                .open(format!("./{}", &out_name))
-                .unwrap();
-            file.write_all(&data).unwrap();
+            let device = bus.lock().unwrap().find("file_import_export").unwrap();
+            let mut device = FileImportExportCard { device, bus };
+
+            device.reset().unwrap();
+            if let Err(e) = import_dir_checked(&mut device, dest, verify) {
+                eprintln!("{}", e);
+            }
         }
         &_ => {
             // let device = bus.lock().unwrap().find("redstone").unwrap();
@@ -137,6 +357,33 @@ fn main() {
     }
 }
 
+fn print_import_state(state: ImportState) {
+    match state {
+        ImportState::Pending => println!("waiting for the player to pick a file..."),
+        ImportState::Accepted => println!("importing"),
+        ImportState::Denied | ImportState::Cancelled | ImportState::TimedOut => (),
+    }
+}
+
+fn print_progress(done: u64, total: Option<u64>) {
+    const WIDTH: u64 = 30;
+    match total {
+        Some(total) if total > 0 => {
+            let filled = (done * WIDTH / total).min(WIDTH) as usize;
+            print!(
+                "\r[{}{}] {:>3}% ({}/{} bytes)",
+                "#".repeat(filled),
+                " ".repeat(WIDTH as usize - filled),
+                done * 100 / total,
+                done,
+                total
+            );
+        }
+        _ => print!("\r{} bytes", done),
+    }
+    let _ = io::stdout().flush();
+}
+
 fn print_devices(bus: Mutex<RPCBus>) {
     let mut bus = bus.lock().unwrap();
     for d in bus.list().unwrap() {
@@ -144,6 +391,51 @@ fn print_devices(bus: Mutex<RPCBus>) {
     }
 }
 
+/// Unpacks the archive the player picks into `dest` and prints its hash. With `verify` it is
+/// checked against a manifest first, nothing is unpacked before that, so the archive is kept in a
+/// temporary file until then.
+fn import_dir_checked(
+    device: &mut FileImportExportCard,
+    dest: &Path,
+    verify: bool,
+) -> io::Result<()> {
+    let session = device
+        .import(ImportOptions::default(), print_import_state)
+        .map_err(io::Error::other)?;
+    let mut reader = HashingReader::new(ImportReader::new(session));
+    if !verify {
+        unpack_all(&mut reader, dest)?;
+        println!("{}", reader.digest());
+        return Ok(());
+    }
+
+    let tmp_path = env::temp_dir().join(format!("oc-import-dir.{}.part", process::id()));
+    let mut tmp = File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&tmp_path)?;
+    let copied = io::copy(&mut reader, &mut tmp);
+    let digest = reader.digest();
+    drop(reader);
+    let result = copied.and_then(|_| {
+        println!("{}", digest);
+
+        println!("pick the manifest to verify against");
+        device
+            .import(ImportOptions::default(), print_import_state)
+            .and_then(import_manifest)
+            .and_then(|m| m.verify(&digest))
+            .map_err(|e| io::Error::other(format!("verification failed: {}", e)))?;
+        println!("verified");
+
+        tmp.rewind()?;
+        unpack(&mut tmp, dest)
+    });
+    let _ = fs::remove_file(&tmp_path);
+    result
+}
+
 fn print_methods(bus: Mutex<RPCBus>) {
     let mut bus = bus.lock().unwrap();
     for d in bus.list().unwrap() {
@@ -152,3 +444,194 @@ fn print_methods(bus: Mutex<RPCBus>) {
         }
     }
 }
+
+/// `redstone record <file> [seconds] [interval ms]` or `redstone play <file>`, files ending in
+/// `.csv` are csv, everything else json lines.
+fn redstone(bus: Mutex<RPCBus>, args: &[String]) {
+    let path = match args.get(1) {
+        Some(p) => Path::new(p),
+        None => {
+            println!("usage: redstone record|play <file>");
+            return;
+        }
+    };
+    let format = Format::from_path(path);
+    let device = bus.lock().unwrap().find("redstone").unwrap();
+    let device = RedstoneInterface { device, bus };
+
+    match args[0].as_str() {
+        "record" => {
+            let seconds = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(10);
+            let interval = args.get(3).and_then(|a| a.parse().ok()).unwrap_or(50);
+            let mut writer = SampleWriter::new(File::create(path).unwrap(), format);
+            record(
+                &device,
+                Duration::from_millis(interval),
+                Some(Duration::from_secs(seconds)),
+                |sample| {
+                    writer.write(sample)?;
+                    Ok(true)
+                },
+            )
+            .unwrap();
+        }
+        "play" => {
+            let file = io::BufReader::new(File::open(path).unwrap());
+            let samples = read_samples(file, format).unwrap();
+            play(&device, &samples).unwrap();
+        }
+        _ => println!("usage: redstone record|play <file>"),
+    }
+}
+
+/// `signal list`, `signal get <name>` or `signal set <name> <level|on|off>`, the signals are read
+/// from the file in `OC_SIGNALS`, `signals.json` by default.
+fn signal(bus: Mutex<RPCBus>, args: &[String]) {
+    let path = env::var("OC_SIGNALS").unwrap_or_else(|_| "signals.json".to_string());
+    let config = match SignalsConfig::load(Path::new(&path)) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("could not load {}: {}", path, e);
+            return;
+        }
+    };
+    let signals = match Signals::new(&bus, &config) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
+    let result = match (
+        args.first().map(|a| a.as_str()),
+        args.get(1),
+        args.get(2).map(|a| a.as_str()),
+    ) {
+        (Some("list"), _, _) => {
+            for name in config.signals.keys() {
+                println!("{}", name);
+            }
+            Ok(())
+        }
+        (Some("get"), Some(name), _) => signals.read(name).map(|l| println!("{}", l)),
+        (Some("set"), Some(name), Some("on")) => signals.set(name, true),
+        (Some("set"), Some(name), Some("off")) => signals.set(name, false),
+        (Some("set"), Some(name), Some(level)) => match level.parse() {
+            Ok(level) => signals.write(name, level),
+            Err(_) => {
+                println!("invalid level {}", level);
+                Ok(())
+            }
+        },
+        _ => {
+            println!("usage: signal list | get <name> | set <name> <level|on|off>");
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+}
+
+fn sound(bus: Mutex<RPCBus>, args: &[String]) {
+    let device = bus.lock().unwrap().find("sound_card").unwrap();
+    let mut card = SoundCard { device, bus };
+    let volume = |i: usize| match args.get(i) {
+        Some(v) => v
+            .parse()
+            .map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("invalid volume {}", v))
+            })
+            .and_then(|v| Ok(Volume::new(v)?)),
+        None => Ok(Volume::default()),
+    };
+
+    let result = match (args.first().map(|a| a.as_str()), args.get(1), args.get(2)) {
+        (Some("find"), Some(name), _) => card.find_sound(name).map(|sounds| {
+            for sound in sounds {
+                println!("{}", sound);
+            }
+        }),
+        (Some("play"), Some(name), _) => {
+            let pitch = match args.get(3) {
+                Some(p) => p
+                    .parse()
+                    .map_err(|_| {
+                        io::Error::new(io::ErrorKind::InvalidInput, format!("invalid pitch {}", p))
+                    })
+                    .and_then(|p| Ok(Pitch::new(p)?)),
+                None => Ok(Pitch::default()),
+            };
+            ResourceLocation::parse(name)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+                .and_then(|sound| card.play_sound_with(&sound, volume(2)?, pitch?))
+        }
+        (Some("note"), Some(instrument), Some(note)) => instrument
+            .parse()
+            .and_then(|instrument: Instrument| match note.parse() {
+                Ok(semitone) => Note::new(instrument, semitone),
+                Err(_) => Note::parse(instrument, note),
+            })
+            .map_err(io::Error::from)
+            .and_then(|note| card.play_note(&note, volume(3)?)),
+        _ => {
+            println!("usage: sound find <text> | play <sound> [volume] [pitch] | note <instrument> <note|semitone> [volume]");
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+    }
+}
+
+/// `bench <device> <method> [iterations] [params...]`, every param is parsed as json, falling
+/// back to a plain string.
+fn bench(bus: Mutex<RPCBus>, args: &[String]) {
+    let (device, method) = match (args.first(), args.get(1)) {
+        (Some(d), Some(m)) => (d, m.as_str()),
+        _ => {
+            println!("usage: bench <device> <method> [iterations] [params...]");
+            return;
+        }
+    };
+    let iterations: u32 = match args.get(2).map(|i| i.parse()) {
+        Some(Ok(i)) => i,
+        Some(Err(_)) => {
+            println!("invalid iteration count");
+            return;
+        }
+        None => 100,
+    };
+    let params: Vec<Value> = args
+        .iter()
+        .skip(3)
+        .map(|p| serde_json::from_str(p).unwrap_or_else(|_| Value::String(p.clone())))
+        .collect();
+
+    let mut bus = bus.lock().unwrap();
+    let device = bus.find(device).unwrap();
+    bus.enable_metrics();
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        if let Err(e) = bus.invoke::<_, Option<Value>>(device, method, &params) {
+            eprintln!("call failed: {}", e);
+        }
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{} calls in {:?} ({:.1} calls/s)",
+        iterations,
+        elapsed,
+        iterations as f64 / elapsed.as_secs_f64()
+    );
+    if let Some(stats) = bus
+        .metrics_snapshot()
+        .as_ref()
+        .and_then(|m| m.get(device, method))
+    {
+        print!("{}", stats);
+    }
+}
@@ -1,3 +1,7 @@
+#[cfg(feature = "redstone_interface")]
+pub mod redstone;
 pub mod rpc_device;
+#[cfg(feature = "file_transfer")]
+pub mod transfer;
 pub mod util;
 pub use rpc_device::device_bus;
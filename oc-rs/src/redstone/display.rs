@@ -0,0 +1,482 @@
+use std::io;
+use std::ops::Deref;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::device_bus::RPCBus;
+use crate::redstone::output::MAX_LEVEL;
+use crate::rpc_device::redstone_interface::set_redstone_output;
+use crate::rpc_device::RPCDevice;
+use crate::util::Side;
+
+/// A single lamp (or segment), wired to `side` of the redstone interface `device`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Output {
+    pub device: RPCDevice,
+    pub side: Side,
+}
+
+/// Remembers what every output was last set to, so a flush only sends the ones that changed.
+struct OutputBank<B> {
+    bus: B,
+    outputs: Vec<Option<Output>>,
+    levels: Vec<usize>,
+    written: Vec<Option<usize>>,
+}
+
+impl<B: Deref<Target = Mutex<RPCBus>>> OutputBank<B> {
+    fn new(bus: B, outputs: Vec<Option<Output>>) -> Self {
+        Self {
+            bus,
+            levels: vec![0; outputs.len()],
+            written: vec![None; outputs.len()],
+            outputs,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<usize> {
+        let mut bus = self.bus.lock().unwrap();
+        let mut count = 0;
+        for (i, output) in self.outputs.iter().enumerate() {
+            let output = match output {
+                Some(o) => o,
+                None => continue,
+            };
+            if self.written[i] != Some(self.levels[i]) {
+                set_redstone_output(&mut bus, output.device, output.side, self.levels[i])?;
+                self.written[i] = Some(self.levels[i]);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    fn invalidate(&mut self) {
+        self.written.iter_mut().for_each(|w| *w = None);
+    }
+}
+
+/// Rows of a 3x5 glyph, the highest of the three bits is the leftmost pixel.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0; 5],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+/// A grid of lamps, drawn into a buffer and sent with [`LampMatrix::flush`].
+///
+/// Text uses a 3x5 font with one column of spacing, so every character is 4 pixels wide.
+pub struct LampMatrix<B> {
+    bank: OutputBank<B>,
+    width: usize,
+    height: usize,
+}
+
+impl<B: Deref<Target = Mutex<RPCBus>>> LampMatrix<B> {
+    /// `outputs` lists the lamps row by row starting at the top left, `None` for gaps in the
+    /// grid.
+    pub fn new(
+        bus: B,
+        width: usize,
+        height: usize,
+        outputs: Vec<Option<Output>>,
+    ) -> io::Result<Self> {
+        if outputs.len() != width * height {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "a {}x{} matrix needs {} outputs, got {}",
+                    width,
+                    height,
+                    width * height,
+                    outputs.len()
+                ),
+            ));
+        }
+        Ok(Self {
+            bank: OutputBank::new(bus, outputs),
+            width,
+            height,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Pixels outside the matrix are ignored.
+    pub fn set_pixel(&mut self, x: usize, y: usize, level: usize) {
+        if x < self.width && y < self.height {
+            self.bank.levels[y * self.width + x] = level.min(MAX_LEVEL);
+        }
+    }
+
+    pub fn fill(&mut self, level: usize) {
+        self.bank
+            .levels
+            .iter_mut()
+            .for_each(|l| *l = level.min(MAX_LEVEL));
+    }
+
+    pub fn clear(&mut self) {
+        self.fill(0);
+    }
+
+    /// Draws `c` with its top left corner at `x`, `y`.
+    pub fn draw_char(&mut self, x: usize, y: usize, c: char, level: usize) {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..3 {
+                let on = bits & (0b100 >> col) != 0;
+                self.set_pixel(x + col, y + row, if on { level } else { 0 });
+            }
+        }
+    }
+
+    /// Draws `text` from `x`, `y` on, whatever doesn't fit is cut off.
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, level: usize) {
+        for (i, c) in text.chars().enumerate() {
+            self.draw_char(x + i * 4, y, c, level);
+        }
+    }
+
+    /// Draws a vertical bar in column `x` filled from the bottom to `value` (`0.0..=1.0`), the
+    /// topmost lamp of the bar gets a partial level for the part of a pixel left over.
+    pub fn draw_bar(&mut self, x: usize, value: f64, level: usize) {
+        let filled = value.clamp(0.0, 1.0) * self.height as f64;
+        for i in 0..self.height {
+            let coverage = (filled - i as f64).clamp(0.0, 1.0);
+            let y = self.height - 1 - i;
+            self.set_pixel(x, y, (coverage * level as f64).round() as usize);
+        }
+    }
+
+    /// One bar per column, starting at the left.
+    pub fn draw_bar_graph(&mut self, values: &[f64], level: usize) {
+        for (x, value) in values.iter().enumerate().take(self.width) {
+            self.draw_bar(x, *value, level);
+        }
+    }
+
+    /// Sends every lamp that changed since the last flush, returns how many were sent.
+    pub fn flush(&mut self) -> io::Result<usize> {
+        self.bank.flush()
+    }
+
+    /// Forgets what the lamps were set to, so the next flush sends all of them.
+    pub fn invalidate(&mut self) {
+        self.bank.invalidate();
+    }
+}
+
+/// The segments of a seven segment digit, in the order `a` to `g` and an optional decimal
+/// point.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DigitOutputs {
+    pub segments: [Option<Output>; 7],
+    pub dot: Option<Output>,
+}
+
+/// The lit segments of `c`, bit 0 is segment `a`. `None` if the character can't be shown.
+fn segments(c: char) -> Option<u8> {
+    Some(match c {
+        '0' | 'O' => 0x3f,
+        '1' | 'I' => 0x06,
+        '2' => 0x5b,
+        '3' => 0x4f,
+        '4' => 0x66,
+        '5' | 'S' | 's' => 0x6d,
+        '6' => 0x7d,
+        '7' => 0x07,
+        '8' | 'B' => 0x7f,
+        '9' | 'g' => 0x6f,
+        'A' | 'a' => 0x77,
+        'b' => 0x7c,
+        'C' => 0x39,
+        'c' => 0x58,
+        'd' | 'D' => 0x5e,
+        'E' | 'e' => 0x79,
+        'F' | 'f' => 0x71,
+        'H' => 0x76,
+        'h' => 0x74,
+        'J' | 'j' => 0x1e,
+        'L' | 'l' => 0x38,
+        'n' | 'N' => 0x54,
+        'o' => 0x5c,
+        'P' | 'p' => 0x73,
+        'r' | 'R' => 0x50,
+        't' | 'T' => 0x78,
+        'U' => 0x3e,
+        'u' => 0x1c,
+        'y' | 'Y' => 0x6e,
+        '-' => 0x40,
+        '_' => 0x08,
+        ' ' => 0x00,
+        _ => return None,
+    })
+}
+
+/// A row of seven segment digits, the first digit is the leftmost one.
+pub struct SevenSegment<B> {
+    bank: OutputBank<B>,
+    digits: usize,
+}
+
+impl<B: Deref<Target = Mutex<RPCBus>>> SevenSegment<B> {
+    pub fn new(bus: B, digits: Vec<DigitOutputs>) -> Self {
+        let count = digits.len();
+        let outputs = digits
+            .into_iter()
+            .flat_map(|d| d.segments.into_iter().chain([d.dot]))
+            .collect();
+        Self {
+            bank: OutputBank::new(bus, outputs),
+            digits: count,
+        }
+    }
+
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+
+    fn set_digit(&mut self, digit: usize, segments: u8, dot: bool, level: usize) {
+        let base = digit * 8;
+        for i in 0..7 {
+            self.bank.levels[base + i] = if segments & (1 << i) != 0 { level } else { 0 };
+        }
+        self.bank.levels[base + 7] = if dot { level } else { 0 };
+    }
+
+    /// Shows `text` right aligned, a `.` lights the decimal point of the character before it.
+    /// Fails without changing anything if a character can't be shown or the text doesn't fit.
+    pub fn show_text(&mut self, text: &str, level: usize) -> io::Result<()> {
+        let mut cells: Vec<(u8, bool)> = Vec::new();
+        for c in text.chars() {
+            match (c, cells.last_mut()) {
+                ('.', Some((_, dot))) if !*dot => *dot = true,
+                ('.', _) => cells.push((0, true)),
+                _ => match segments(c) {
+                    Some(s) => cells.push((s, false)),
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("can't show {:?} on a seven segment display", c),
+                        ))
+                    }
+                },
+            }
+        }
+        if cells.len() > self.digits {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} doesn't fit on {} digits", text, self.digits),
+            ));
+        }
+
+        let level = level.min(MAX_LEVEL);
+        let padding = self.digits - cells.len();
+        for digit in 0..padding {
+            self.set_digit(digit, 0, false, level);
+        }
+        for (i, (segments, dot)) in cells.into_iter().enumerate() {
+            self.set_digit(padding + i, segments, dot, level);
+        }
+        Ok(())
+    }
+
+    pub fn show_number(&mut self, number: i64, level: usize) -> io::Result<()> {
+        self.show_text(&number.to_string(), level)
+    }
+
+    pub fn clear(&mut self) {
+        self.bank.levels.iter_mut().for_each(|l| *l = 0);
+    }
+
+    /// Sends every segment that changed since the last flush, returns how many were sent.
+    pub fn flush(&mut self) -> io::Result<usize> {
+        self.bank.flush()
+    }
+
+    /// Forgets what the segments were set to, so the next flush sends all of them.
+    pub fn invalidate(&mut self) {
+        self.bank.invalidate();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_bus::tests::{bus, send, sent};
+
+    fn seven_segment(bus: &Mutex<RPCBus>, digits: usize) -> SevenSegment<&Mutex<RPCBus>> {
+        let unwired = DigitOutputs {
+            segments: [None; 7],
+            dot: None,
+        };
+        SevenSegment::new(bus, vec![unwired; digits])
+    }
+
+    /// The segments and decimal point lit on every digit.
+    fn shown(display: &SevenSegment<&Mutex<RPCBus>>) -> Vec<(u8, bool)> {
+        display
+            .bank
+            .levels
+            .chunks(8)
+            .map(|d| {
+                let segments = (0..7).filter(|&i| d[i] > 0).fold(0, |s, i| s | 1 << i);
+                (segments, d[7] > 0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dots_merge_into_the_digit_before() {
+        let (bus, _other) = bus();
+        let bus = Mutex::new(bus);
+        let mut display = seven_segment(&bus, 4);
+
+        display.show_text("1.2.", 15).unwrap();
+        assert_eq!(
+            shown(&display),
+            vec![(0, false), (0, false), (0x06, true), (0x5b, true)]
+        );
+        display.show_text("1..2", 15).unwrap();
+        assert_eq!(
+            shown(&display),
+            vec![(0, false), (0x06, true), (0, true), (0x5b, false)]
+        );
+        display.show_text(".5", 15).unwrap();
+        assert_eq!(
+            shown(&display),
+            vec![(0, false), (0, false), (0, true), (0x6d, false)]
+        );
+    }
+
+    #[test]
+    fn text_is_right_aligned() {
+        let (bus, _other) = bus();
+        let bus = Mutex::new(bus);
+        let mut display = seven_segment(&bus, 3);
+
+        display.show_text("888", 15).unwrap();
+        display.show_number(-7, 9).unwrap();
+        assert_eq!(
+            shown(&display),
+            vec![(0, false), (0x40, false), (0x07, false)]
+        );
+        assert_eq!(display.bank.levels[2 * 8..2 * 8 + 3], [9, 9, 9]);
+    }
+
+    #[test]
+    fn text_that_does_not_fit() {
+        let (bus, _other) = bus();
+        let bus = Mutex::new(bus);
+        let mut display = seven_segment(&bus, 3);
+        display.show_text("12", 15).unwrap();
+        let before = shown(&display);
+
+        let err = display.show_text("1234", 15).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(display.show_text("1.2.3.4", 15).is_err());
+        assert!(display.show_text("1?", 15).is_err());
+        assert_eq!(shown(&display), before);
+        assert!(display.show_text("1.2.3.", 15).is_ok());
+    }
+
+    #[test]
+    fn bar_has_a_partial_top_lamp() {
+        let (bus, _other) = bus();
+        let bus = Mutex::new(bus);
+        let mut matrix = LampMatrix::new(&bus, 1, 4, vec![None; 4]).unwrap();
+
+        matrix.draw_bar(0, 0.6, 15);
+        // 2.4 of 4 lamps, the third one from the bottom gets 0.4 of the level.
+        assert_eq!(matrix.bank.levels, [0, 6, 15, 15]);
+        matrix.draw_bar(0, 1.5, 10);
+        assert_eq!(matrix.bank.levels, [10; 4]);
+        matrix.draw_bar(0, 0.0, 15);
+        assert_eq!(matrix.bank.levels, [0; 4]);
+    }
+
+    #[test]
+    fn flush_sends_only_changes() {
+        let (bus, mut other) = bus();
+        let bus = Mutex::new(bus);
+        let lamp = |side| {
+            Some(Output {
+                device: Default::default(),
+                side,
+            })
+        };
+        let mut bank = OutputBank::new(&bus, vec![lamp(Side::Up), None, lamp(Side::Down)]);
+        let ok = r#"{"type":"result","data":null}"#;
+
+        for _ in 0..2 {
+            send(&mut other, ok);
+        }
+        assert_eq!(bank.flush().unwrap(), 2);
+        assert_eq!(sent(&mut other).matches("setRedstoneOutput").count(), 2);
+
+        assert_eq!(bank.flush().unwrap(), 0);
+        bank.levels[1] = 15;
+        assert_eq!(bank.flush().unwrap(), 0);
+
+        send(&mut other, ok);
+        bank.levels[2] = 7;
+        assert_eq!(bank.flush().unwrap(), 1);
+        let sent_now = sent(&mut other);
+        assert!(sent_now.contains(r#"["down",7]"#), "{}", sent_now);
+        assert!(!sent_now.contains("\"up\""));
+
+        bank.invalidate();
+        for _ in 0..2 {
+            send(&mut other, ok);
+        }
+        assert_eq!(bank.flush().unwrap(), 2);
+        assert_eq!(sent(&mut other).matches("setRedstoneOutput").count(), 2);
+    }
+}
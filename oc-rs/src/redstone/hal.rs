@@ -0,0 +1,161 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use embedded_hal::{digital, pwm};
+
+use crate::rpc_device::RedstoneInterface;
+use crate::util::Side;
+
+/// The highest signal strength, the `max_duty_cycle` of an output pin.
+const MAX_LEVEL: u16 = 15;
+
+/// A failed call on the bus, as both a digital and a pwm error.
+#[derive(Debug)]
+pub struct PinError(pub io::Error);
+
+impl Display for PinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "redstone pin: {}", self.0)
+    }
+}
+
+impl std::error::Error for PinError {}
+
+impl digital::Error for PinError {
+    fn kind(&self) -> digital::ErrorKind {
+        digital::ErrorKind::Other
+    }
+}
+
+impl pwm::Error for PinError {
+    fn kind(&self) -> pwm::ErrorKind {
+        pwm::ErrorKind::Other
+    }
+}
+
+impl From<io::Error> for PinError {
+    fn from(e: io::Error) -> Self {
+        PinError(e)
+    }
+}
+
+impl RedstoneInterface {
+    /// The input of `side` as a pin, high for any signal.
+    pub fn input_pin(&self, side: Side) -> InputPin<'_> {
+        InputPin {
+            interface: self,
+            side,
+            threshold: 1,
+        }
+    }
+
+    /// The output of `side` as a pin, high is full strength.
+    pub fn output_pin(&self, side: Side) -> OutputPin<'_> {
+        OutputPin {
+            interface: self,
+            side,
+        }
+    }
+}
+
+/// A redstone input, implements [`digital::InputPin`] and reads the analog level like an ADC with
+/// [`InputPin::level`].
+pub struct InputPin<'a> {
+    interface: &'a RedstoneInterface,
+    side: Side,
+    threshold: usize,
+}
+
+impl InputPin<'_> {
+    /// The pin reads high once the signal is at least `threshold` strong.
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// The signal strength, `0..=15`.
+    pub fn level(&mut self) -> Result<u16, PinError> {
+        Ok(self.interface.get_redstone_input(self.side)? as u16)
+    }
+}
+
+impl digital::ErrorType for InputPin<'_> {
+    type Error = PinError;
+}
+
+impl digital::InputPin for InputPin<'_> {
+    fn is_high(&mut self) -> Result<bool, PinError> {
+        Ok(self.interface.get_redstone_input(self.side)? >= self.threshold)
+    }
+
+    fn is_low(&mut self) -> Result<bool, PinError> {
+        Ok(!self.is_high()?)
+    }
+}
+
+/// A redstone output, implements [`digital::OutputPin`] and [`pwm::SetDutyCycle`] with the 16
+/// signal strengths as duty cycles.
+pub struct OutputPin<'a> {
+    interface: &'a RedstoneInterface,
+    side: Side,
+}
+
+impl OutputPin<'_> {
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// What the output is currently set to.
+    pub fn level(&mut self) -> Result<u16, PinError> {
+        Ok(self.interface.get_redstone_output(self.side)? as u16)
+    }
+
+    /// Sets the signal strength, clamped to `0..=15`.
+    pub fn set_level(&mut self, level: u16) -> Result<(), PinError> {
+        self.interface
+            .set_redstone_output(self.side, level.min(MAX_LEVEL) as usize)?;
+        Ok(())
+    }
+}
+
+impl digital::ErrorType for OutputPin<'_> {
+    type Error = PinError;
+}
+
+impl digital::OutputPin for OutputPin<'_> {
+    fn set_low(&mut self) -> Result<(), PinError> {
+        self.set_level(0)
+    }
+
+    fn set_high(&mut self) -> Result<(), PinError> {
+        self.set_level(MAX_LEVEL)
+    }
+}
+
+impl digital::StatefulOutputPin for OutputPin<'_> {
+    fn is_set_high(&mut self) -> Result<bool, PinError> {
+        Ok(self.level()? > 0)
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, PinError> {
+        Ok(self.level()? == 0)
+    }
+}
+
+impl pwm::ErrorType for OutputPin<'_> {
+    type Error = PinError;
+}
+
+impl pwm::SetDutyCycle for OutputPin<'_> {
+    fn max_duty_cycle(&self) -> u16 {
+        MAX_LEVEL
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), PinError> {
+        self.set_level(duty)
+    }
+}
@@ -0,0 +1,17 @@
+pub mod display;
+#[cfg(feature = "embedded_hal")]
+pub mod hal;
+pub mod output;
+pub mod plc;
+pub mod recorder;
+pub mod serial;
+pub mod signals;
+pub mod watcher;
+
+pub use display::{LampMatrix, SevenSegment};
+pub use output::{OutputHandle, Step};
+pub use plc::{Plc, PlcConfig};
+pub use recorder::Sample;
+pub use serial::{LinkConfig, RedstoneLink};
+pub use signals::{Signals, SignalsConfig};
+pub use watcher::{EdgeKind, RedstoneEvent, RedstoneWatcher, WatchHandle, WatcherConfig};
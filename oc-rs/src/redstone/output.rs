@@ -0,0 +1,273 @@
+use std::io;
+use std::ops::Deref;
+use std::sync::mpsc;
+use std::sync::mpsc::{RecvTimeoutError, Sender};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::rpc_device::RedstoneInterface;
+use crate::util::Side;
+
+/// The highest signal strength.
+pub const MAX_LEVEL: usize = 15;
+
+/// Hold `level` for `duration`. Minecraft only updates redstone every tick (50ms), shorter steps
+/// may never be seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub level: usize,
+    pub duration: Duration,
+}
+
+impl Step {
+    /// Fails if `level` is above [`MAX_LEVEL`].
+    pub fn new(level: usize, duration: Duration) -> io::Result<Self> {
+        check_level(level)?;
+        Ok(Self { level, duration })
+    }
+}
+
+/// A single pulse of `level` for `duration`.
+pub fn pulse<D>(
+    interface: D,
+    side: Side,
+    level: usize,
+    duration: Duration,
+) -> io::Result<OutputHandle>
+where
+    D: Deref<Target = RedstoneInterface> + Send + 'static,
+{
+    sequence(interface, side, vec![Step::new(level, duration)?], false)
+}
+
+/// A clock that is at `level` for `duty` (`0.0..=1.0`) of every `period` and off for the rest.
+pub fn clock<D>(
+    interface: D,
+    side: Side,
+    level: usize,
+    period: Duration,
+    duty: f64,
+) -> io::Result<OutputHandle>
+where
+    D: Deref<Target = RedstoneInterface> + Send + 'static,
+{
+    sequence(interface, side, clock_steps(level, period, duty)?, true)
+}
+
+fn clock_steps(level: usize, period: Duration, duty: f64) -> io::Result<Vec<Step>> {
+    check_finite("duty", duty)?;
+    let on = period.mul_f64(duty.clamp(0.0, 1.0));
+    Ok(vec![Step::new(level, on)?, Step::new(0, period - on)?])
+}
+
+/// Software PWM for signal strengths between the 16 levels, `value` (`0.0..=15.0`) is output as
+/// the two levels around it, switching every `period` so the average matches.
+pub fn pwm<D>(interface: D, side: Side, value: f64, period: Duration) -> io::Result<OutputHandle>
+where
+    D: Deref<Target = RedstoneInterface> + Send + 'static,
+{
+    sequence(interface, side, pwm_steps(value, period)?, true)
+}
+
+fn pwm_steps(value: f64, period: Duration) -> io::Result<Vec<Step>> {
+    check_finite("value", value)?;
+    let value = value.clamp(0.0, MAX_LEVEL as f64);
+    let low = value.floor() as usize;
+    let high = period.mul_f64(value.fract());
+    // At 15.0 the high step has no duration, but it still can't be above the highest level.
+    Ok(vec![
+        Step::new((low + 1).min(MAX_LEVEL), high)?,
+        Step::new(low, period - high)?,
+    ])
+}
+
+fn check_level(level: usize) -> io::Result<()> {
+    if level <= MAX_LEVEL {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("level {} is above {}", level, MAX_LEVEL),
+        ))
+    }
+}
+
+/// NaN or infinity would make the step durations panic.
+fn check_finite(name: &str, value: f64) -> io::Result<()> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} has to be a finite number, got {}", name, value),
+        ))
+    }
+}
+
+/// Plays `steps` once, or over and over if `repeat` is set, on a background thread.
+///
+/// Steps with a zero duration are skipped, levels above [`MAX_LEVEL`] are rejected. The output the side had before is restored once the
+/// sequence ends or is stopped.
+pub fn sequence<D>(
+    interface: D,
+    side: Side,
+    steps: Vec<Step>,
+    repeat: bool,
+) -> io::Result<OutputHandle>
+where
+    D: Deref<Target = RedstoneInterface> + Send + 'static,
+{
+    let steps: Vec<Step> = steps
+        .into_iter()
+        .filter(|s| !s.duration.is_zero())
+        .collect();
+    for step in &steps {
+        check_level(step.level)?;
+    }
+    if repeat && steps.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a repeating sequence needs at least one step with a duration",
+        ));
+    }
+    let previous = interface.get_redstone_output(side)?;
+
+    let (stop, stopped) = mpsc::channel::<()>();
+    let thread = thread::spawn(move || {
+        let mut current = previous;
+        let result = (|| loop {
+            for step in &steps {
+                if step.level != current {
+                    interface.set_redstone_output(side, step.level)?;
+                    current = step.level;
+                }
+                match stopped.recv_timeout(step.duration) {
+                    Err(RecvTimeoutError::Timeout) => (),
+                    _ => return Ok(()),
+                }
+            }
+            if !repeat {
+                return Ok(());
+            }
+        })();
+        if current != previous {
+            interface.set_redstone_output(side, previous)?;
+        }
+        result
+    });
+
+    Ok(OutputHandle {
+        stop: Some(stop),
+        thread: Some(thread),
+    })
+}
+
+/// A running output pattern, dropping it stops the pattern like [`OutputHandle::stop`].
+pub struct OutputHandle {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl OutputHandle {
+    /// Whether the pattern ended on its own (or failed).
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Waits for a pattern that doesn't repeat to end.
+    pub fn wait(mut self) -> io::Result<()> {
+        self.join()
+    }
+
+    /// Stops the pattern and restores the previous output.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.stop.take();
+        self.join()
+    }
+
+    fn join(&mut self) -> io::Result<()> {
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("output thread panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for OutputHandle {
+    fn drop(&mut self) {
+        self.stop.take();
+        let _ = self.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERIOD: Duration = Duration::from_millis(1000);
+
+    fn steps(steps: &[(usize, u64)]) -> Vec<Step> {
+        steps
+            .iter()
+            .map(|&(level, ms)| Step::new(level, Duration::from_millis(ms)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn levels_above_max() {
+        let kind = |r: io::Result<Step>| r.unwrap_err().kind();
+        assert_eq!(kind(Step::new(16, PERIOD)), io::ErrorKind::InvalidInput);
+        assert_eq!(kind(Step::new(99, PERIOD)), io::ErrorKind::InvalidInput);
+        assert!(Step::new(MAX_LEVEL, PERIOD).is_ok());
+        assert!(clock_steps(16, PERIOD, 0.5).is_err());
+    }
+
+    #[test]
+    fn clock() {
+        assert_eq!(
+            clock_steps(15, PERIOD, 0.25).unwrap(),
+            steps(&[(15, 250), (0, 750)])
+        );
+        assert_eq!(
+            clock_steps(7, PERIOD, 0.0).unwrap(),
+            steps(&[(7, 0), (0, 1000)])
+        );
+        assert_eq!(
+            clock_steps(7, PERIOD, 1.0).unwrap(),
+            steps(&[(7, 1000), (0, 0)])
+        );
+        assert_eq!(
+            clock_steps(7, PERIOD, 2.0).unwrap(),
+            clock_steps(7, PERIOD, 1.0).unwrap()
+        );
+        assert!(clock_steps(7, PERIOD, f64::NAN).is_err());
+    }
+
+    #[test]
+    fn pwm() {
+        assert_eq!(
+            pwm_steps(7.25, PERIOD).unwrap(),
+            steps(&[(8, 250), (7, 750)])
+        );
+        assert_eq!(
+            pwm_steps(0.5, PERIOD).unwrap(),
+            steps(&[(1, 500), (0, 500)])
+        );
+        assert_eq!(pwm_steps(3.0, PERIOD).unwrap(), steps(&[(4, 0), (3, 1000)]));
+        assert_eq!(
+            pwm_steps(15.0, PERIOD).unwrap(),
+            steps(&[(15, 0), (15, 1000)])
+        );
+        assert_eq!(
+            pwm_steps(20.0, PERIOD).unwrap(),
+            pwm_steps(15.0, PERIOD).unwrap()
+        );
+        assert_eq!(
+            pwm_steps(-1.0, PERIOD).unwrap(),
+            steps(&[(1, 0), (0, 1000)])
+        );
+        assert!(pwm_steps(f64::INFINITY, PERIOD).is_err());
+    }
+}
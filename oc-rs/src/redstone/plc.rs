@@ -0,0 +1,601 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::device_bus::RPCBus;
+use crate::redstone::output::MAX_LEVEL;
+use crate::redstone::signals::resolve_device;
+use crate::rpc_device::redstone_interface::{get_redstone_input, set_redstone_output};
+use crate::rpc_device::RPCDevice;
+use crate::util::Side;
+
+fn default_scan_time_ms() -> u64 {
+    100
+}
+
+/// A PLC program, usually loaded from json:
+///
+/// ```json
+/// {
+///   "inputs": { "start": { "device": "redstone", "side": "north" },
+///               "stop": { "device": "redstone", "side": "south" } },
+///   "outputs": { "motor": { "device": "redstone", "side": "up" } },
+///   "markers": ["running"],
+///   "timers": { "warmup": { "kind": "ton", "preset_ms": 2000 } },
+///   "rungs": [
+///     { "conditions": [{ "contact": "start" }, { "not_contact": "stop" }],
+///       "actions": [{ "set": "running" }] },
+///     { "conditions": [{ "contact": "stop" }], "actions": [{ "reset": "running" }] },
+///     { "conditions": [{ "contact": "running" }], "actions": [{ "timer": "warmup" }] },
+///     { "conditions": [{ "contact": "warmup" }], "actions": [{ "coil": "motor" }] }
+///   ]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlcConfig {
+    #[serde(default = "default_scan_time_ms")]
+    pub scan_time_ms: u64,
+    #[serde(default)]
+    pub inputs: BTreeMap<String, IoConfig>,
+    #[serde(default)]
+    pub outputs: BTreeMap<String, IoConfig>,
+    /// Internal bits that aren't wired anywhere.
+    #[serde(default)]
+    pub markers: Vec<String>,
+    #[serde(default)]
+    pub timers: BTreeMap<String, TimerConfig>,
+    #[serde(default)]
+    pub counters: BTreeMap<String, CounterConfig>,
+    pub rungs: Vec<Rung>,
+}
+
+impl PlcConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IoConfig {
+    /// A device id, or a component name as passed to [`RPCBus::find`].
+    pub device: String,
+    pub side: Side,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimerKind {
+    /// On delay, done once its rung has been true for the preset time.
+    Ton,
+    /// Off delay, done while its rung is true and for the preset time after it turns false.
+    Tof,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerConfig {
+    pub kind: TimerKind,
+    pub preset_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CounterKind {
+    /// Counts up from 0, done once it reaches the preset.
+    Up,
+    /// Counts down from the preset, done once it reaches 0.
+    Down,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterConfig {
+    pub kind: CounterKind,
+    pub preset: i64,
+}
+
+/// One rung of the ladder, the actions get the result of the conditions in series. A rung
+/// without conditions is always true.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Rung {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+}
+
+/// Every name can be an input, output, marker, timer (true when done) or counter (true when
+/// done).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// Normally open, true while the bit is set.
+    Contact(String),
+    /// Normally closed, true while the bit is clear.
+    NotContact(String),
+    /// Parallel branches, true if all conditions of any branch are true.
+    Any(Vec<Vec<Condition>>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Sets an output or marker to the rung result.
+    Coil(String),
+    /// Latches an output or marker on while the rung is true.
+    Set(String),
+    /// Unlatches an output or marker, or resets a timer or counter, while the rung is true.
+    Reset(String),
+    /// Runs a timer with the rung result as its input.
+    Timer(String),
+    /// Counts a counter on every rising edge of the rung.
+    Count(String),
+}
+
+#[derive(Debug, Default)]
+struct TimerState {
+    elapsed: Duration,
+    done: bool,
+}
+
+#[derive(Debug, Default)]
+struct CounterState {
+    count: i64,
+    last_input: bool,
+}
+
+/// A scan cycle runtime: every scan reads all inputs, evaluates the rungs top to bottom (later
+/// rungs see what earlier ones did) and then writes the outputs that changed.
+pub struct Plc<B> {
+    bus: B,
+    config: PlcConfig,
+    inputs: Vec<(String, RPCDevice, Side)>,
+    outputs: Vec<(String, RPCDevice, Side)>,
+    bits: HashMap<String, bool>,
+    written: HashMap<String, bool>,
+    timers: HashMap<String, TimerState>,
+    counters: HashMap<String, CounterState>,
+    last_scan: Option<Instant>,
+}
+
+impl<B: Deref<Target = Mutex<RPCBus>>> Plc<B> {
+    /// Checks the program and resolves every device, so mistakes show up before anything is
+    /// switched.
+    pub fn new(bus: B, config: PlcConfig) -> anyhow::Result<Self> {
+        validate(&config)?;
+
+        let (inputs, outputs) = {
+            let mut bus = bus.lock().unwrap();
+            let mut resolve = |io: &BTreeMap<String, IoConfig>| -> anyhow::Result<Vec<_>> {
+                io.iter()
+                    .map(|(name, c)| {
+                        let device = resolve_device(&mut bus, &c.device)
+                            .map_err(|e| anyhow!("device {} of {}: {}", c.device, name, e))?;
+                        Ok((name.clone(), device, c.side))
+                    })
+                    .collect()
+            };
+            (resolve(&config.inputs)?, resolve(&config.outputs)?)
+        };
+
+        Ok(Self::with_io(bus, config, inputs, outputs))
+    }
+
+    pub fn load(bus: B, path: &Path) -> anyhow::Result<Self> {
+        Self::new(bus, PlcConfig::load(path)?)
+    }
+
+    /// Runs a single scan cycle.
+    pub fn scan(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let dt = self.last_scan.map_or(Duration::ZERO, |l| now - l);
+        self.last_scan = Some(now);
+
+        {
+            let mut bus = self.bus.lock().unwrap();
+            for (name, device, side) in &self.inputs {
+                let level = get_redstone_input(&mut bus, *device, *side)?;
+                self.bits.insert(name.clone(), level > 0);
+            }
+        }
+        self.eval_rungs(dt);
+        self.write_outputs()
+    }
+
+    /// Scans every [`PlcConfig::scan_time_ms`] until `keep_going` returns `false`, then switches
+    /// all outputs off. If a scan fails the outputs are switched off as well (as far as that
+    /// still works) before the error is returned.
+    pub fn run(&mut self, mut keep_going: impl FnMut(&Self) -> bool) -> io::Result<()> {
+        let scan_time = Duration::from_millis(self.config.scan_time_ms);
+        while keep_going(self) {
+            let start = Instant::now();
+            if let Err(e) = self.scan() {
+                let _ = self.shutdown();
+                return Err(e);
+            }
+            thread::sleep(scan_time.saturating_sub(start.elapsed()));
+        }
+        self.shutdown()
+    }
+
+    /// Switches every output off.
+    pub fn shutdown(&mut self) -> io::Result<()> {
+        for (name, _, _) in &self.outputs {
+            self.bits.insert(name.clone(), false);
+        }
+        self.write_outputs()
+    }
+
+    fn write_outputs(&mut self) -> io::Result<()> {
+        let mut bus = self.bus.lock().unwrap();
+        for (name, device, side) in &self.outputs {
+            let on = self.bits[name];
+            if self.written.get(name) != Some(&on) {
+                let level = if on { MAX_LEVEL } else { 0 };
+                set_redstone_output(&mut bus, *device, *side, level)?;
+                self.written.insert(name.clone(), on);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Everything that doesn't touch the bus.
+impl<B> Plc<B> {
+    fn with_io(
+        bus: B,
+        config: PlcConfig,
+        inputs: Vec<(String, RPCDevice, Side)>,
+        outputs: Vec<(String, RPCDevice, Side)>,
+    ) -> Self {
+        let bits = config
+            .inputs
+            .keys()
+            .chain(config.outputs.keys())
+            .chain(config.markers.iter())
+            .map(|n| (n.clone(), false))
+            .collect();
+        let timers = config
+            .timers
+            .keys()
+            .map(|n| (n.clone(), TimerState::default()))
+            .collect();
+        let counters = config
+            .counters
+            .iter()
+            .map(|(n, c)| {
+                let count = match c.kind {
+                    CounterKind::Up => 0,
+                    CounterKind::Down => c.preset,
+                };
+                (
+                    n.clone(),
+                    CounterState {
+                        count,
+                        last_input: false,
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            bus,
+            config,
+            inputs,
+            outputs,
+            bits,
+            written: HashMap::new(),
+            timers,
+            counters,
+            last_scan: None,
+        }
+    }
+
+    pub fn config(&self) -> &PlcConfig {
+        &self.config
+    }
+
+    /// The state of a bit, timer or counter as a contact would see it.
+    pub fn bit(&self, name: &str) -> Option<bool> {
+        if let Some(bit) = self.bits.get(name) {
+            return Some(*bit);
+        }
+        if let Some(timer) = self.timers.get(name) {
+            return Some(timer.done);
+        }
+        let counter = self.counters.get(name)?;
+        Some(match self.config.counters[name].kind {
+            CounterKind::Up => counter.count >= self.config.counters[name].preset,
+            CounterKind::Down => counter.count <= 0,
+        })
+    }
+
+    pub fn timer_elapsed(&self, name: &str) -> Option<Duration> {
+        self.timers.get(name).map(|t| t.elapsed)
+    }
+
+    pub fn counter_value(&self, name: &str) -> Option<i64> {
+        self.counters.get(name).map(|c| c.count)
+    }
+
+    /// Evaluates every rung once, `dt` is the time since the last scan.
+    fn eval_rungs(&mut self, dt: Duration) {
+        // Taken out for the scan so the actions can update the state they are read from.
+        let rungs = std::mem::take(&mut self.config.rungs);
+        for rung in &rungs {
+            let power = self.eval_all(&rung.conditions);
+            for action in &rung.actions {
+                self.apply(action, power, dt);
+            }
+        }
+        self.config.rungs = rungs;
+    }
+
+    fn eval_all(&self, conditions: &[Condition]) -> bool {
+        conditions.iter().all(|c| self.eval(c))
+    }
+
+    fn eval(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Contact(name) => self.bit(name).unwrap_or(false),
+            Condition::NotContact(name) => !self.bit(name).unwrap_or(false),
+            Condition::Any(branches) => branches.iter().any(|b| self.eval_all(b)),
+        }
+    }
+
+    fn apply(&mut self, action: &Action, power: bool, dt: Duration) {
+        match action {
+            Action::Coil(name) => {
+                self.bits.insert(name.clone(), power);
+            }
+            Action::Set(name) => {
+                if power {
+                    self.bits.insert(name.clone(), true);
+                }
+            }
+            Action::Reset(name) => {
+                if !power {
+                    return;
+                }
+                if let Some(bit) = self.bits.get_mut(name) {
+                    *bit = false;
+                } else if let Some(timer) = self.timers.get_mut(name) {
+                    *timer = TimerState::default();
+                } else if let Some(counter) = self.counters.get_mut(name) {
+                    counter.count = match self.config.counters[name].kind {
+                        CounterKind::Up => 0,
+                        CounterKind::Down => self.config.counters[name].preset,
+                    };
+                }
+            }
+            Action::Timer(name) => {
+                let config = self.config.timers[name];
+                let preset = Duration::from_millis(config.preset_ms);
+                let timer = self.timers.get_mut(name).unwrap();
+                match config.kind {
+                    TimerKind::Ton if power => {
+                        timer.elapsed = (timer.elapsed + dt).min(preset);
+                        timer.done = timer.elapsed >= preset;
+                    }
+                    TimerKind::Ton => *timer = TimerState::default(),
+                    TimerKind::Tof if power => {
+                        timer.elapsed = Duration::ZERO;
+                        timer.done = true;
+                    }
+                    TimerKind::Tof => {
+                        if timer.done {
+                            timer.elapsed = (timer.elapsed + dt).min(preset);
+                            timer.done = timer.elapsed < preset;
+                        }
+                    }
+                }
+            }
+            Action::Count(name) => {
+                let kind = self.config.counters[name].kind;
+                let counter = self.counters.get_mut(name).unwrap();
+                if power && !counter.last_input {
+                    counter.count += match kind {
+                        CounterKind::Up => 1,
+                        CounterKind::Down => -1,
+                    };
+                }
+                counter.last_input = power;
+            }
+        }
+    }
+}
+
+/// Makes sure every name is declared once and only used where it makes sense.
+fn validate(config: &PlcConfig) -> anyhow::Result<()> {
+    let mut names = HashSet::new();
+    let all = config
+        .inputs
+        .keys()
+        .chain(config.outputs.keys())
+        .chain(config.markers.iter())
+        .chain(config.timers.keys())
+        .chain(config.counters.keys());
+    for name in all {
+        if !names.insert(name.as_str()) {
+            return Err(anyhow!("{} is declared more than once", name));
+        }
+    }
+
+    fn check_conditions(names: &HashSet<&str>, conditions: &[Condition]) -> anyhow::Result<()> {
+        for c in conditions {
+            match c {
+                Condition::Contact(n) | Condition::NotContact(n) => {
+                    if !names.contains(n.as_str()) {
+                        return Err(anyhow!("unknown name {}", n));
+                    }
+                }
+                Condition::Any(branches) => {
+                    for b in branches {
+                        check_conditions(names, b)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    let writable =
+        |n: &str| config.outputs.contains_key(n) || config.markers.iter().any(|m| m == n);
+    for (i, rung) in config.rungs.iter().enumerate() {
+        check_conditions(&names, &rung.conditions).map_err(|e| anyhow!("rung {}: {}", i, e))?;
+        for action in &rung.actions {
+            let ok = match action {
+                Action::Coil(n) | Action::Set(n) => writable(n),
+                Action::Reset(n) => {
+                    writable(n) || config.timers.contains_key(n) || config.counters.contains_key(n)
+                }
+                Action::Timer(n) => config.timers.contains_key(n),
+                Action::Count(n) => config.counters.contains_key(n),
+            };
+            if !ok {
+                return Err(anyhow!("rung {}: invalid action {:?}", i, action));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: Duration = Duration::from_millis(40);
+
+    fn plc(config: serde_json::Value) -> Plc<()> {
+        let config: PlcConfig = serde_json::from_value(config).unwrap();
+        validate(&config).unwrap();
+        Plc::with_io((), config, Vec::new(), Vec::new())
+    }
+
+    /// Sets the marker `input` and runs one scan that took `DT`.
+    fn scan(plc: &mut Plc<()>, input: bool) {
+        plc.bits.insert("input".to_string(), input);
+        plc.eval_rungs(DT);
+    }
+
+    fn timer(kind: &str) -> Plc<()> {
+        plc(serde_json::json!({
+            "markers": ["input"],
+            "timers": { "t": { "kind": kind, "preset_ms": 100 } },
+            "rungs": [{ "conditions": [{ "contact": "input" }], "actions": [{ "timer": "t" }] }]
+        }))
+    }
+
+    #[test]
+    fn on_delay_timer() {
+        let mut plc = timer("ton");
+        scan(&mut plc, true);
+        scan(&mut plc, true);
+        assert_eq!(plc.bit("t"), Some(false));
+        assert_eq!(plc.timer_elapsed("t"), Some(Duration::from_millis(80)));
+        scan(&mut plc, true);
+        assert_eq!(plc.bit("t"), Some(true));
+        assert_eq!(plc.timer_elapsed("t"), Some(Duration::from_millis(100)));
+        scan(&mut plc, true);
+        assert_eq!(plc.bit("t"), Some(true));
+
+        scan(&mut plc, false);
+        assert_eq!(plc.bit("t"), Some(false));
+        assert_eq!(plc.timer_elapsed("t"), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn off_delay_timer() {
+        let mut plc = timer("tof");
+        scan(&mut plc, false);
+        assert_eq!(plc.bit("t"), Some(false));
+        scan(&mut plc, true);
+        assert_eq!(plc.bit("t"), Some(true));
+
+        scan(&mut plc, false);
+        scan(&mut plc, false);
+        assert_eq!(plc.bit("t"), Some(true));
+        // Energising it again restarts the delay.
+        scan(&mut plc, true);
+        scan(&mut plc, false);
+        scan(&mut plc, false);
+        assert_eq!(plc.bit("t"), Some(true));
+        scan(&mut plc, false);
+        assert_eq!(plc.bit("t"), Some(false));
+        scan(&mut plc, false);
+        assert_eq!(plc.bit("t"), Some(false));
+    }
+
+    #[test]
+    fn set_and_reset_latch() {
+        let mut plc = plc(serde_json::json!({
+            "markers": ["start", "stop", "running"],
+            "rungs": [
+                { "conditions": [{ "contact": "start" }], "actions": [{ "set": "running" }] },
+                { "conditions": [{ "contact": "stop" }], "actions": [{ "reset": "running" }] }
+            ]
+        }));
+        let mut press = |name: &str, on: bool| {
+            plc.bits.insert(name.to_string(), on);
+            plc.eval_rungs(DT);
+            plc.bit("running").unwrap()
+        };
+        assert!(press("start", true));
+        assert!(press("start", false));
+        // Reset wins while both are pressed, it comes later.
+        assert!(!press("stop", true));
+        assert!(!press("start", true));
+        assert!(press("stop", false));
+        assert!(press("start", false));
+        assert!(!press("stop", true));
+    }
+
+    #[test]
+    fn counters_count_rising_edges() {
+        let mut plc = plc(serde_json::json!({
+            "markers": ["input", "clear"],
+            "counters": {
+                "up": { "kind": "up", "preset": 2 },
+                "down": { "kind": "down", "preset": 2 }
+            },
+            "rungs": [
+                { "conditions": [{ "contact": "input" }],
+                  "actions": [{ "count": "up" }, { "count": "down" }] },
+                { "conditions": [{ "contact": "clear" }],
+                  "actions": [{ "reset": "up" }, { "reset": "down" }] }
+            ]
+        }));
+        assert_eq!(plc.counter_value("down"), Some(2));
+
+        scan(&mut plc, true);
+        scan(&mut plc, true);
+        scan(&mut plc, true);
+        assert_eq!(plc.counter_value("up"), Some(1));
+        assert_eq!(plc.counter_value("down"), Some(1));
+        assert_eq!(plc.bit("up"), Some(false));
+
+        scan(&mut plc, false);
+        scan(&mut plc, true);
+        assert_eq!(plc.counter_value("up"), Some(2));
+        assert_eq!(plc.counter_value("down"), Some(0));
+        assert_eq!(plc.bit("up"), Some(true));
+        assert_eq!(plc.bit("down"), Some(true));
+
+        plc.apply(&Action::Reset("up".to_string()), true, DT);
+        plc.apply(&Action::Reset("down".to_string()), true, DT);
+        assert_eq!(plc.counter_value("up"), Some(0));
+        assert_eq!(plc.counter_value("down"), Some(2));
+        // A rung that isn't powered doesn't reset anything.
+        plc.apply(&Action::Count("up".to_string()), false, DT);
+        plc.apply(&Action::Count("up".to_string()), true, DT);
+        plc.apply(&Action::Reset("up".to_string()), false, DT);
+        assert_eq!(plc.counter_value("up"), Some(1));
+    }
+}
@@ -0,0 +1,316 @@
+use std::io;
+use std::io::{BufRead, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::rpc_device::RedstoneInterface;
+use crate::util::Side;
+
+const CSV_HEADER: &str = "time_ms,up,down,north,east,south,west";
+
+/// The input levels of all six sides at `time_ms` milliseconds into a recording.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sample {
+    pub time_ms: u64,
+    pub up: usize,
+    pub down: usize,
+    pub north: usize,
+    pub east: usize,
+    pub south: usize,
+    pub west: usize,
+}
+
+impl Sample {
+    pub fn level(&self, side: Side) -> usize {
+        match side {
+            Side::Up => self.up,
+            Side::Down => self.down,
+            Side::North => self.north,
+            Side::East => self.east,
+            Side::South => self.south,
+            Side::West => self.west,
+        }
+    }
+
+    pub fn set_level(&mut self, side: Side, level: usize) {
+        match side {
+            Side::Up => self.up = level,
+            Side::Down => self.down = level,
+            Side::North => self.north = level,
+            Side::East => self.east = level,
+            Side::South => self.south = level,
+            Side::West => self.west = level,
+        }
+    }
+
+    fn same_levels(&self, other: &Sample) -> bool {
+        Side::all().all(|s| self.level(s) == other.level(s))
+    }
+
+    fn to_csv(self) -> String {
+        format!(
+            "{},{},{},{},{},{},{}",
+            self.time_ms, self.up, self.down, self.north, self.east, self.south, self.west
+        )
+    }
+
+    fn from_csv(line: &str) -> io::Result<Sample> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid sample {:?}", line),
+            )
+        };
+        let mut fields = line.split(',').map(|f| f.trim().parse::<u64>());
+        let mut next = || fields.next().ok_or_else(invalid)?.map_err(|_| invalid());
+        let mut sample = Sample {
+            time_ms: next()?,
+            ..Sample::default()
+        };
+        for side in Side::ALL {
+            sample.set_level(side, next()? as usize);
+        }
+        if fields.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(sample)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Jsonl,
+}
+
+impl Format {
+    /// `.csv` files are csv, everything else is json lines.
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("csv") => Format::Csv,
+            _ => Format::Jsonl,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "jsonl" | "json" => Ok(Format::Jsonl),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown format {}", s),
+            )),
+        }
+    }
+}
+
+/// Writes samples one per line, with a header first for csv.
+pub struct SampleWriter<W> {
+    out: W,
+    format: Format,
+    header_written: bool,
+}
+
+impl<W: Write> SampleWriter<W> {
+    pub fn new(out: W, format: Format) -> Self {
+        Self {
+            out,
+            format,
+            header_written: false,
+        }
+    }
+
+    pub fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        match self.format {
+            Format::Csv => {
+                if !self.header_written {
+                    writeln!(self.out, "{}", CSV_HEADER)?;
+                    self.header_written = true;
+                }
+                writeln!(self.out, "{}", sample.to_csv())
+            }
+            Format::Jsonl => {
+                serde_json::to_writer(&mut self.out, sample)?;
+                writeln!(self.out)
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Reads a whole recording, in the order it was written. Only the first line of a csv recording
+/// may be the header.
+pub fn read_samples<R: BufRead>(input: R, format: Format) -> io::Result<Vec<Sample>> {
+    let mut samples = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || (format == Format::Csv && i == 0 && line == CSV_HEADER) {
+            continue;
+        }
+        samples.push(match format {
+            Format::Csv => Sample::from_csv(line)?,
+            Format::Jsonl => serde_json::from_str(line)?,
+        });
+    }
+    Ok(samples)
+}
+
+/// Samples all six inputs every `interval` for `duration` (or forever) and hands every sample
+/// that differs from the previous one to `on_sample`, until it returns `false`.
+///
+/// The first sample is always handed over, and once `duration` is up a last one with the
+/// current levels, so a playback lasts as long as the recording.
+pub fn record<D>(
+    interface: D,
+    interval: Duration,
+    duration: Option<Duration>,
+    mut on_sample: impl FnMut(&Sample) -> io::Result<bool>,
+) -> io::Result<()>
+where
+    D: Deref<Target = RedstoneInterface>,
+{
+    let start = Instant::now();
+    let mut last: Option<Sample> = None;
+    loop {
+        let tick = Instant::now();
+        let mut sample = Sample {
+            time_ms: start.elapsed().as_millis() as u64,
+            ..Sample::default()
+        };
+        for side in Side::ALL {
+            sample.set_level(side, interface.get_redstone_input(side)?);
+        }
+        let done = duration.is_some_and(|d| start.elapsed() >= d);
+        if done || !last.is_some_and(|l| l.same_levels(&sample)) {
+            last = Some(sample);
+            if !on_sample(&sample)? || done {
+                return Ok(());
+            }
+        }
+        thread::sleep(interval.saturating_sub(tick.elapsed()));
+    }
+}
+
+/// Replays `samples` onto the outputs with their original timing, blocking until the last one.
+/// Each input side is played back on the output of the same side, and the outputs are restored
+/// to what they were before once done.
+pub fn play<D>(interface: D, samples: &[Sample]) -> io::Result<()>
+where
+    D: Deref<Target = RedstoneInterface>,
+{
+    let mut previous = Sample::default();
+    for side in Side::ALL {
+        previous.set_level(side, interface.get_redstone_output(side)?);
+    }
+
+    let start = Instant::now();
+    let mut current = previous;
+    let result = (|| {
+        for sample in samples {
+            let at = Duration::from_millis(sample.time_ms);
+            thread::sleep(at.saturating_sub(start.elapsed()));
+            for side in Side::ALL {
+                if current.level(side) != sample.level(side) {
+                    interface.set_redstone_output(side, sample.level(side))?;
+                    current.set_level(side, sample.level(side));
+                }
+            }
+        }
+        Ok(())
+    })();
+
+    for side in Side::ALL {
+        if current.level(side) != previous.level(side) {
+            interface.set_redstone_output(side, previous.level(side))?;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Vec<Sample> {
+        vec![
+            Sample::default(),
+            Sample {
+                time_ms: 50,
+                up: 15,
+                west: 3,
+                ..Sample::default()
+            },
+            Sample {
+                time_ms: 1200,
+                down: 1,
+                north: 2,
+                east: 4,
+                south: 8,
+                ..Sample::default()
+            },
+        ]
+    }
+
+    fn round_trip(format: Format) -> String {
+        let mut writer = SampleWriter::new(Vec::new(), format);
+        for sample in samples() {
+            writer.write(&sample).unwrap();
+        }
+        let data = writer.into_inner();
+        assert_eq!(read_samples(&data[..], format).unwrap(), samples());
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let csv = round_trip(Format::Csv);
+        assert_eq!(csv.lines().next(), Some(CSV_HEADER));
+        assert_eq!(csv.lines().nth(2), Some("50,15,0,0,0,0,3"));
+    }
+
+    #[test]
+    fn jsonl_round_trip() {
+        let jsonl = round_trip(Format::Jsonl);
+        assert_eq!(jsonl.lines().count(), 3);
+    }
+
+    #[test]
+    fn malformed_lines() {
+        let kind = |data: &str, format| read_samples(data.as_bytes(), format).map_err(|e| e.kind());
+        for line in [
+            "1,2,3,4,5,6,7,8",
+            "1,2,3,4,5,6",
+            "1,2,3,x,5,6,7",
+            "",
+            "-1,0,0,0,0,0,0",
+        ] {
+            let data = format!("{}\n{}\n", CSV_HEADER, line);
+            let expected = if line.is_empty() {
+                Ok(vec![])
+            } else {
+                Err(io::ErrorKind::InvalidData)
+            };
+            assert_eq!(kind(&data, Format::Csv), expected, "{:?}", line);
+        }
+        // The header is only allowed on the first line.
+        let data = format!("{}\n{}\n", CSV_HEADER, CSV_HEADER);
+        assert_eq!(kind(&data, Format::Csv), Err(io::ErrorKind::InvalidData));
+        assert_eq!(
+            kind("{\"time_ms\":1}\n", Format::Jsonl),
+            Err(io::ErrorKind::InvalidData)
+        );
+    }
+}
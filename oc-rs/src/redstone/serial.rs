@@ -0,0 +1,437 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::{Read, Write};
+use std::ops::Deref;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::rpc_device::RedstoneInterface;
+use crate::util::Side;
+
+/// Marks the start of every frame.
+const START: u8 = 0x7e;
+const KIND_DATA: u8 = 1;
+const KIND_ACK: u8 = 2;
+/// Start, kind, sequence number and length.
+const HEADER_LEN: usize = 4;
+
+/// Which sides a [`RedstoneLink`] uses and how fast it talks.
+///
+/// Every data side carries 4 bits as its signal strength, so the wiring between the two computers
+/// has to deliver the exact strength (direct contact or comparators, not a long line of dust).
+/// The clock side toggles between 0 and 15 once per symbol, so it can be plain dust. The `tx`
+/// sides of one computer are wired to the `rx` sides of the other.
+#[derive(Debug, Clone)]
+pub struct LinkConfig {
+    pub tx_data: Vec<Side>,
+    pub tx_clock: Side,
+    pub rx_data: Vec<Side>,
+    pub rx_clock: Side,
+    /// How long every symbol is held, has to be a few game ticks so the other side sees every
+    /// clock toggle.
+    pub symbol_time: Duration,
+    pub poll_interval: Duration,
+    /// How long to wait for the other side to acknowledge a frame before sending it again.
+    pub ack_timeout: Duration,
+    /// How often a frame is sent again before the write fails.
+    pub retries: u32,
+    /// The most bytes sent in a single frame.
+    pub max_payload: u8,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            tx_data: vec![Side::North],
+            tx_clock: Side::East,
+            rx_data: vec![Side::South],
+            rx_clock: Side::West,
+            symbol_time: Duration::from_millis(150),
+            poll_interval: Duration::from_millis(10),
+            ack_timeout: Duration::from_secs(5),
+            retries: 5,
+            max_payload: 32,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Frame {
+    kind: u8,
+    seq: u8,
+    payload: Vec<u8>,
+}
+
+/// CRC-8 with the polynomial `x^8 + x^2 + x + 1`.
+fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |mut crc, byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn encode_frame(kind: u8, seq: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len() + 1);
+    frame.extend_from_slice(&[START, kind, seq, payload.len() as u8]);
+    frame.extend_from_slice(payload);
+    frame.push(crc8(&frame[1..]));
+    frame
+}
+
+/// Splits a frame into the symbols sent for it, padding the last one with zeros.
+fn encode_symbols(frame: &[u8], sides: usize) -> Vec<Vec<u8>> {
+    let nibbles: Vec<u8> = frame.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect();
+    nibbles
+        .chunks(sides)
+        .map(|symbol| {
+            let mut symbol = symbol.to_vec();
+            symbol.resize(sides, 0);
+            symbol
+        })
+        .collect()
+}
+
+/// Reassembles frames from received symbols, dropping anything that doesn't check out.
+#[derive(Debug, Default)]
+struct FrameParser {
+    buf: Vec<u8>,
+    high_nibble: Option<u8>,
+    max_payload: u8,
+}
+
+impl FrameParser {
+    /// Nibbles are paired up across symbols, a byte can span two of them when there are fewer
+    /// than two data sides. The rest of the symbol a frame ends in is padding and dropped.
+    fn feed_symbol(&mut self, nibbles: &[u8]) -> Option<Frame> {
+        for &nibble in nibbles {
+            let Some(high) = self.high_nibble.take() else {
+                self.high_nibble = Some(nibble);
+                continue;
+            };
+            let byte = (high << 4) | nibble;
+            if self.buf.is_empty() && byte != START {
+                // Still looking for a frame, slide on by one nibble so a start that isn't lined
+                // up with the pairing is found as well.
+                self.high_nibble = Some(nibble);
+                continue;
+            }
+            if let Some(frame) = self.feed_byte(byte) {
+                self.high_nibble = None;
+                return Some(frame);
+            }
+        }
+        None
+    }
+
+    fn feed_byte(&mut self, byte: u8) -> Option<Frame> {
+        if self.buf.is_empty() {
+            if byte == START {
+                self.buf.push(byte);
+            }
+            return None;
+        }
+        self.buf.push(byte);
+        if self.buf.len() < HEADER_LEN {
+            return None;
+        }
+        let len = self.buf[3] as usize;
+        if len > self.max_payload as usize {
+            self.buf.clear();
+            self.high_nibble = None;
+            return None;
+        }
+        if self.buf.len() < HEADER_LEN + len + 1 {
+            return None;
+        }
+
+        let frame = std::mem::take(&mut self.buf);
+        let (body, crc) = frame.split_at(frame.len() - 1);
+        if crc8(&body[1..]) != crc[0] {
+            self.high_nibble = None;
+            return None;
+        }
+        Some(Frame {
+            kind: body[1],
+            seq: body[2],
+            payload: body[HEADER_LEN..].to_vec(),
+        })
+    }
+}
+
+/// A reliable byte stream between two computers over plain redstone.
+///
+/// Data is sent in frames with a CRC-8, every frame is acknowledged by the other side and sent
+/// again if the acknowledgement doesn't arrive. Both directions work at the same time, but only
+/// while one of the ends is inside a `read` or `write`, nothing is received in between.
+pub struct RedstoneLink<D> {
+    interface: D,
+    config: LinkConfig,
+    parser: FrameParser,
+    tx_levels: Vec<usize>,
+    tx_clock: bool,
+    rx_clock: bool,
+    tx_seq: u8,
+    last_rx_seq: Option<u8>,
+    last_ack: Option<u8>,
+    pending_acks: VecDeque<u8>,
+    sending: bool,
+    received: VecDeque<u8>,
+    read_timeout: Option<Duration>,
+}
+
+impl<D: Deref<Target = RedstoneInterface>> RedstoneLink<D> {
+    /// Resets the outputs of the link and starts listening.
+    pub fn new(interface: D, config: LinkConfig) -> io::Result<Self> {
+        if config.tx_data.is_empty() || config.rx_data.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a link needs at least one data side in each direction",
+            ));
+        }
+        for side in config.tx_data.iter().chain([&config.tx_clock]) {
+            interface.set_redstone_output(*side, 0)?;
+        }
+        let rx_clock = interface.get_redstone_input(config.rx_clock)? >= 8;
+
+        Ok(Self {
+            parser: FrameParser {
+                max_payload: config.max_payload,
+                ..FrameParser::default()
+            },
+            tx_levels: vec![0; config.tx_data.len()],
+            tx_clock: false,
+            rx_clock,
+            tx_seq: 0,
+            last_rx_seq: None,
+            last_ack: None,
+            pending_acks: VecDeque::new(),
+            sending: false,
+            received: VecDeque::new(),
+            read_timeout: None,
+            interface,
+            config,
+        })
+    }
+
+    /// How long a read waits for data, `None` (the default) waits forever.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    /// Checks for a clock toggle on the receiving side and takes in the symbol if there was one.
+    fn poll(&mut self) -> io::Result<()> {
+        let clock = self.interface.get_redstone_input(self.config.rx_clock)? >= 8;
+        if clock == self.rx_clock {
+            return Ok(());
+        }
+        self.rx_clock = clock;
+
+        let mut nibbles = Vec::with_capacity(self.config.rx_data.len());
+        for side in &self.config.rx_data {
+            nibbles.push(self.interface.get_redstone_input(*side)?.min(15) as u8);
+        }
+        if let Some(frame) = self.parser.feed_symbol(&nibbles) {
+            match frame.kind {
+                KIND_DATA => {
+                    // A repeated frame means our acknowledgement got lost, acknowledge it again
+                    // but don't deliver it twice.
+                    if self.last_rx_seq != Some(frame.seq) {
+                        self.received.extend(frame.payload);
+                        self.last_rx_seq = Some(frame.seq);
+                    }
+                    self.pending_acks.push_back(frame.seq);
+                }
+                KIND_ACK => self.last_ack = Some(frame.seq),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Sleeps for `duration` while still receiving.
+    fn wait(&mut self, duration: Duration) -> io::Result<()> {
+        let deadline = Instant::now() + duration;
+        loop {
+            self.poll()?;
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            thread::sleep(left.min(self.config.poll_interval));
+        }
+    }
+
+    fn send_frame(&mut self, kind: u8, seq: u8, payload: &[u8]) -> io::Result<()> {
+        let symbols = encode_symbols(&encode_frame(kind, seq, payload), self.config.tx_data.len());
+
+        self.sending = true;
+        let result = (|| {
+            for symbol in &symbols {
+                for (i, level) in symbol.iter().enumerate() {
+                    let level = *level as usize;
+                    if self.tx_levels[i] != level {
+                        self.interface
+                            .set_redstone_output(self.config.tx_data[i], level)?;
+                        self.tx_levels[i] = level;
+                    }
+                }
+                self.wait(self.config.symbol_time / 2)?;
+                self.tx_clock = !self.tx_clock;
+                self.interface.set_redstone_output(
+                    self.config.tx_clock,
+                    if self.tx_clock { 15 } else { 0 },
+                )?;
+                self.wait(self.config.symbol_time / 2)?;
+            }
+            Ok(())
+        })();
+        self.sending = false;
+        result
+    }
+
+    /// Sends the acknowledgements that piled up while a frame was being sent.
+    fn flush_acks(&mut self) -> io::Result<()> {
+        if self.sending {
+            return Ok(());
+        }
+        while let Some(seq) = self.pending_acks.pop_front() {
+            self.send_frame(KIND_ACK, seq, &[])?;
+        }
+        Ok(())
+    }
+
+    fn wait_for_ack(&mut self, seq: u8) -> io::Result<bool> {
+        let deadline = Instant::now() + self.config.ack_timeout;
+        while Instant::now() < deadline {
+            self.poll()?;
+            self.flush_acks()?;
+            if self.last_ack == Some(seq) {
+                return Ok(true);
+            }
+            thread::sleep(self.config.poll_interval);
+        }
+        Ok(false)
+    }
+}
+
+impl<D: Deref<Target = RedstoneInterface>> Read for RedstoneLink<D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = self.read_timeout.map(|t| Instant::now() + t);
+        while self.received.is_empty() && !buf.is_empty() {
+            self.poll()?;
+            self.flush_acks()?;
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            thread::sleep(self.config.poll_interval);
+        }
+
+        let len = buf.len().min(self.received.len());
+        for (dst, src) in buf.iter_mut().zip(self.received.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl<D: Deref<Target = RedstoneInterface>> Write for RedstoneLink<D> {
+    /// Sends up to [`LinkConfig::max_payload`] bytes as a single frame and waits for it to be
+    /// acknowledged.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.config.max_payload.max(1) as usize);
+        let seq = self.tx_seq;
+        for _ in 0..=self.config.retries {
+            self.flush_acks()?;
+            self.send_frame(KIND_DATA, seq, &buf[..len])?;
+            if self.wait_for_ack(seq)? {
+                self.tx_seq = seq.wrapping_add(1);
+                return Ok(len);
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the other side did not acknowledge the frame",
+        ))
+    }
+
+    /// Every write is acknowledged before it returns, so there is nothing to flush.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser() -> FrameParser {
+        FrameParser {
+            max_payload: 32,
+            ..FrameParser::default()
+        }
+    }
+
+    fn feed(parser: &mut FrameParser, frame: &[u8], sides: usize) -> Option<Frame> {
+        let mut result = None;
+        for symbol in encode_symbols(frame, sides) {
+            if let Some(frame) = parser.feed_symbol(&symbol) {
+                assert!(result.is_none(), "more than one frame decoded");
+                result = Some(frame);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn round_trip() {
+        for sides in 1..=4 {
+            let mut parser = parser();
+            // Some noise before the frame starts.
+            parser.feed_symbol(&vec![0; sides]);
+            parser.feed_symbol(&vec![3; sides]);
+            for (kind, seq, payload) in [(KIND_DATA, 0, &b"hi"[..]), (KIND_ACK, 7, &[][..])] {
+                let frame = feed(&mut parser, &encode_frame(kind, seq, payload), sides)
+                    .unwrap_or_else(|| panic!("no frame with {} data sides", sides));
+                assert_eq!(frame.kind, kind);
+                assert_eq!(frame.seq, seq);
+                assert_eq!(frame.payload, payload);
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_crc() {
+        for sides in 1..=4 {
+            let mut parser = parser();
+            let mut bytes = encode_frame(KIND_DATA, 1, b"hello");
+            *bytes.last_mut().unwrap() ^= 0x01;
+            assert!(feed(&mut parser, &bytes, sides).is_none());
+
+            let frame = feed(&mut parser, &encode_frame(KIND_DATA, 2, b"ok"), sides).unwrap();
+            assert_eq!(frame.seq, 2);
+            assert_eq!(frame.payload, b"ok");
+        }
+    }
+
+    #[test]
+    fn oversized_length() {
+        for sides in 1..=4 {
+            let mut parser = parser();
+            assert!(feed(&mut parser, &encode_frame(KIND_DATA, 1, &[0x55; 40]), sides).is_none());
+
+            let frame = feed(&mut parser, &encode_frame(KIND_DATA, 2, b"ok"), sides).unwrap();
+            assert_eq!(frame.seq, 2);
+            assert_eq!(frame.payload, b"ok");
+        }
+    }
+}
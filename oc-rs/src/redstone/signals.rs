@@ -0,0 +1,211 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::device_bus::RPCBus;
+use crate::redstone::output::MAX_LEVEL;
+use crate::rpc_device::redstone_interface::{
+    get_redstone_input, get_redstone_output, set_redstone_output,
+};
+use crate::rpc_device::RPCDevice;
+use crate::util::Side;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Input,
+    Output,
+}
+
+/// Where a named signal lives.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignalConfig {
+    /// A device id, or a component name as passed to [`RPCBus::find`].
+    pub device: String,
+    pub side: Side,
+    pub direction: Direction,
+}
+
+/// Maps signal names to where they are wired, stored as json:
+///
+/// ```json
+/// {
+///   "signals": {
+///     "coolant_valve": { "device": "3f2a...", "side": "north", "direction": "output" }
+///   }
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SignalsConfig {
+    pub signals: BTreeMap<String, SignalConfig>,
+}
+
+impl SignalsConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Looks up a device given either as its id or as a component name.
+pub(crate) fn resolve_device(bus: &mut RPCBus, selector: &str) -> io::Result<RPCDevice> {
+    match Uuid::parse_str(selector) {
+        Ok(id) => Ok(id),
+        Err(_) => bus.find(selector),
+    }
+}
+
+/// A signal with its device resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signal {
+    pub device: RPCDevice,
+    pub side: Side,
+    pub direction: Direction,
+}
+
+/// Reads and writes redstone signals by name, across any number of redstone interfaces on the
+/// bus.
+pub struct Signals<B> {
+    bus: B,
+    signals: HashMap<String, Signal>,
+}
+
+impl<B: Deref<Target = Mutex<RPCBus>>> Signals<B> {
+    /// Resolves every device in `config`, fails if one of them can't be found.
+    pub fn new(bus: B, config: &SignalsConfig) -> anyhow::Result<Self> {
+        let mut signals = HashMap::new();
+        {
+            let mut bus = bus.lock().unwrap();
+            for (name, signal) in &config.signals {
+                let device = resolve_device(&mut bus, &signal.device)
+                    .map_err(|e| anyhow!("device {} of signal {}: {}", signal.device, name, e))?;
+                signals.insert(
+                    name.clone(),
+                    Signal {
+                        device,
+                        side: signal.side,
+                        direction: signal.direction,
+                    },
+                );
+            }
+        }
+        Ok(Self { bus, signals })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Signal> {
+        self.signals.get(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.signals.keys().map(|n| n.as_str())
+    }
+
+    fn signal(&self, name: &str) -> anyhow::Result<Signal> {
+        self.get(name)
+            .copied()
+            .ok_or_else(|| anyhow!("unknown signal {}", name))
+    }
+
+    /// The input level of an input, or what an output is currently set to.
+    pub fn read(&self, name: &str) -> anyhow::Result<usize> {
+        let signal = self.signal(name)?;
+        let mut bus = self.bus.lock().unwrap();
+        Ok(match signal.direction {
+            Direction::Input => get_redstone_input(&mut bus, signal.device, signal.side)?,
+            Direction::Output => get_redstone_output(&mut bus, signal.device, signal.side)?,
+        })
+    }
+
+    /// Sets an output, levels above [`MAX_LEVEL`] are clamped.
+    pub fn write(&self, name: &str, level: usize) -> anyhow::Result<()> {
+        let signal = self.signal(name)?;
+        if signal.direction != Direction::Output {
+            return Err(anyhow!("signal {} is an input", name));
+        }
+        let mut bus = self.bus.lock().unwrap();
+        set_redstone_output(&mut bus, signal.device, signal.side, level.min(MAX_LEVEL))?;
+        Ok(())
+    }
+
+    pub fn is_on(&self, name: &str) -> anyhow::Result<bool> {
+        Ok(self.read(name)? > 0)
+    }
+
+    /// Sets an output to full strength or off.
+    pub fn set(&self, name: &str, on: bool) -> anyhow::Result<()> {
+        self.write(name, if on { MAX_LEVEL } else { 0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_bus::tests::{bus, send, sent};
+
+    const VALVE: &str = "3f2a6c1e-8d4b-4f0a-9c3e-5b7d1a2e4f60";
+
+    fn config() -> SignalsConfig {
+        serde_json::from_str(&format!(
+            r#"{{
+                "signals": {{
+                    "valve": {{ "device": "{0}", "side": "north", "direction": "output" }},
+                    "sensor": {{ "device": "{0}", "side": "up", "direction": "input" }}
+                }}
+            }}"#,
+            VALVE
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn config_load_save() {
+        let path = std::env::temp_dir().join(format!("oc-rs-signals-{}.json", std::process::id()));
+        let config = config();
+        config.save(&path).unwrap();
+        let loaded = SignalsConfig::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, config);
+        let valve = &loaded.signals["valve"];
+        assert_eq!(valve.side, Side::North);
+        assert_eq!(valve.direction, Direction::Output);
+        assert_eq!(loaded.signals["sensor"].direction, Direction::Input);
+    }
+
+    #[test]
+    fn inputs_are_not_written() {
+        let (bus, mut other) = bus();
+        let bus = Mutex::new(bus);
+        let signals = Signals::new(&bus, &config()).unwrap();
+        assert_eq!(signals.get("sensor").unwrap().device.to_string(), VALVE);
+
+        assert!(signals.write("sensor", 3).is_err());
+        assert!(signals.set("sensor", true).is_err());
+        assert!(signals.write("missing", 3).is_err());
+        assert_eq!(sent(&mut other), "");
+    }
+
+    #[test]
+    fn levels_are_clamped() {
+        let (bus, mut other) = bus();
+        let bus = Mutex::new(bus);
+        let signals = Signals::new(&bus, &config()).unwrap();
+        send(&mut other, r#"{"type":"result","data":null}"#);
+
+        signals.write("valve", 99).unwrap();
+        let sent = sent(&mut other);
+        assert!(sent.contains(r#"["north",15]"#), "{}", sent);
+    }
+}
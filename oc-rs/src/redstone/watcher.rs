@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use std::io;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::redstone::output::MAX_LEVEL;
+use crate::rpc_device::RedstoneInterface;
+use crate::util::Side;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// The side went from low to high, see [`WatcherConfig::high`].
+    Rising,
+    /// The side went from high to low, see [`WatcherConfig::low`].
+    Falling,
+    /// The signal strength changed at all.
+    Level,
+}
+
+/// A change seen by a [`RedstoneWatcher`], a single change can produce both a
+/// [`EdgeKind::Level`] and an edge event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedstoneEvent {
+    pub side: Side,
+    pub kind: EdgeKind,
+    pub old: usize,
+    pub new: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct WatcherConfig {
+    pub sides: Vec<Side>,
+    /// How often the inputs are read, one game tick by default.
+    pub interval: Duration,
+    /// How long a new signal strength has to be stable before it is reported, zero reports every
+    /// change right away. Otherwise it can't be shorter than `interval`, levels are only seen
+    /// once per poll.
+    pub debounce: Duration,
+    /// A low side goes high once its signal is at least this strong, has to be in `1..=15`.
+    pub high: usize,
+    /// A high side goes low once its signal is at most this strong, anything between `low` and
+    /// `high` keeps the previous state. Has to be below `high`, or a level between the two would
+    /// flip the side on every poll.
+    pub low: usize,
+}
+
+impl WatcherConfig {
+    /// Fails with [`io::ErrorKind::InvalidInput`] on thresholds or times that can't work.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |message: String| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        if self.low >= self.high {
+            return invalid(format!(
+                "low ({}) has to be below high ({})",
+                self.low, self.high
+            ));
+        }
+        if self.high > MAX_LEVEL {
+            return invalid(format!("high ({}) is above {}", self.high, MAX_LEVEL));
+        }
+        if self.interval.is_zero() {
+            return invalid("the poll interval can't be zero".to_string());
+        }
+        if !self.debounce.is_zero() && self.debounce < self.interval {
+            return invalid(format!(
+                "a debounce of {:?} is shorter than the poll interval of {:?}",
+                self.debounce, self.interval
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for WatcherConfig {
+    fn default() -> Self {
+        Self {
+            sides: Side::ALL.to_vec(),
+            interval: Duration::from_millis(50),
+            debounce: Duration::ZERO,
+            high: 1,
+            low: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SideState {
+    level: usize,
+    high: bool,
+    /// A new level that has not been stable for the debounce time yet, and since when it was
+    /// seen.
+    pending: Option<(usize, Instant)>,
+}
+
+impl SideState {
+    fn new(config: &WatcherConfig, level: usize) -> Self {
+        Self {
+            level,
+            high: level >= config.high,
+            pending: None,
+        }
+    }
+
+    /// Takes in the level read at `now`, adding the events it causes to `events`.
+    fn update(
+        &mut self,
+        config: &WatcherConfig,
+        side: Side,
+        level: usize,
+        now: Instant,
+        events: &mut Vec<RedstoneEvent>,
+    ) {
+        if level == self.level {
+            self.pending = None;
+            return;
+        }
+        if !config.debounce.is_zero() {
+            match self.pending {
+                Some((pending, since)) if pending == level && now - since >= config.debounce => {}
+                Some((pending, _)) if pending == level => return,
+                _ => {
+                    self.pending = Some((level, now));
+                    return;
+                }
+            }
+        }
+
+        let old = self.level;
+        self.level = level;
+        self.pending = None;
+        let event = |kind| RedstoneEvent {
+            side,
+            kind,
+            old,
+            new: level,
+        };
+        events.push(event(EdgeKind::Level));
+        if !self.high && level >= config.high {
+            self.high = true;
+            events.push(event(EdgeKind::Rising));
+        } else if self.high && level <= config.low {
+            self.high = false;
+            events.push(event(EdgeKind::Falling));
+        }
+    }
+}
+
+/// Polls the inputs of a [`RedstoneInterface`] and turns them into [`RedstoneEvent`]s.
+///
+/// Works with anything that derefs to the interface, a plain reference to poll it in a loop or
+/// an `Arc` to [`spawn`](RedstoneWatcher::spawn) it on a thread of its own.
+pub struct RedstoneWatcher<D> {
+    interface: D,
+    config: WatcherConfig,
+    states: HashMap<Side, SideState>,
+}
+
+impl<D: Deref<Target = RedstoneInterface>> RedstoneWatcher<D> {
+    /// Fails if the config doesn't [`validate`](WatcherConfig::validate).
+    pub fn new(interface: D, config: WatcherConfig) -> io::Result<Self> {
+        config.validate()?;
+        Ok(Self {
+            interface,
+            config,
+            states: HashMap::new(),
+        })
+    }
+
+    pub fn config(&self) -> &WatcherConfig {
+        &self.config
+    }
+
+    /// The last reported signal strength of `side`, `None` before the first poll.
+    pub fn level(&self, side: Side) -> Option<usize> {
+        self.states.get(&side).map(|s| s.level)
+    }
+
+    /// Reads every watched side once and returns what changed, the first poll only records the
+    /// current levels.
+    pub fn poll(&mut self) -> io::Result<Vec<RedstoneEvent>> {
+        let now = Instant::now();
+        let mut events = Vec::new();
+        for &side in &self.config.sides {
+            let level = self.interface.get_redstone_input(side)?;
+            match self.states.get_mut(&side) {
+                Some(state) => state.update(&self.config, side, level, now, &mut events),
+                None => {
+                    self.states
+                        .insert(side, SideState::new(&self.config, level));
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    /// Polls every [`WatcherConfig::interval`] and calls `on_event` for every event until it
+    /// returns `false` or reading an input fails.
+    pub fn run(&mut self, mut on_event: impl FnMut(RedstoneEvent) -> bool) -> io::Result<()> {
+        loop {
+            let start = Instant::now();
+            for event in self.poll()? {
+                if !on_event(event) {
+                    return Ok(());
+                }
+            }
+            thread::sleep(self.config.interval.saturating_sub(start.elapsed()));
+        }
+    }
+}
+
+impl<D: Deref<Target = RedstoneInterface> + Send + 'static> RedstoneWatcher<D> {
+    /// Runs the watcher on a background thread until the returned handle is stopped or dropped.
+    pub fn spawn(
+        mut self,
+        mut on_event: impl FnMut(RedstoneEvent) + Send + 'static,
+    ) -> WatchHandle {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = thread::spawn(move || {
+            while !stopped.load(Ordering::Relaxed) {
+                let start = Instant::now();
+                self.poll()?.into_iter().for_each(&mut on_event);
+                thread::sleep(self.config.interval.saturating_sub(start.elapsed()));
+            }
+            Ok(())
+        });
+        WatchHandle {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Stops a spawned watcher when dropped.
+pub struct WatchHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl WatchHandle {
+    /// Stops the watcher and returns the error it stopped with, if any.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.join()
+    }
+
+    fn join(&mut self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("watcher thread panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(50);
+
+    /// Feeds `levels` one poll interval apart, returning the events of every poll.
+    fn feed(config: &WatcherConfig, levels: &[usize]) -> Vec<Vec<(EdgeKind, usize, usize)>> {
+        let start = Instant::now();
+        let mut state = SideState::new(config, levels[0]);
+        levels[1..]
+            .iter()
+            .enumerate()
+            .map(|(i, &level)| {
+                let mut events = Vec::new();
+                let now = start + TICK * (i as u32 + 1);
+                state.update(config, Side::Up, level, now, &mut events);
+                events.iter().map(|e| (e.kind, e.old, e.new)).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn edge_kinds() {
+        use EdgeKind::*;
+        let config = WatcherConfig::default();
+        assert_eq!(
+            feed(&config, &[0, 0, 5, 7, 0]),
+            vec![
+                vec![],
+                vec![(Level, 0, 5), (Rising, 0, 5)],
+                vec![(Level, 5, 7)],
+                vec![(Level, 7, 0), (Falling, 7, 0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn hysteresis() {
+        use EdgeKind::*;
+        let config = WatcherConfig {
+            high: 10,
+            low: 5,
+            ..WatcherConfig::default()
+        };
+        // Nothing between 5 and 10 changes the state, whichever way it is crossed.
+        assert_eq!(
+            feed(&config, &[0, 7, 10, 7, 6, 5, 9]),
+            vec![
+                vec![(Level, 0, 7)],
+                vec![(Level, 7, 10), (Rising, 7, 10)],
+                vec![(Level, 10, 7)],
+                vec![(Level, 7, 6)],
+                vec![(Level, 6, 5), (Falling, 6, 5)],
+                vec![(Level, 5, 9)],
+            ]
+        );
+    }
+
+    #[test]
+    fn debounce() {
+        use EdgeKind::*;
+        let config = WatcherConfig {
+            debounce: TICK * 2,
+            ..WatcherConfig::default()
+        };
+        assert_eq!(
+            feed(&config, &[0, 15, 0, 15, 15, 15, 3, 3, 3]),
+            vec![
+                // A single tick glitch is never reported.
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+                vec![(Level, 0, 15), (Rising, 0, 15)],
+                vec![],
+                vec![],
+                vec![(Level, 15, 3)],
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_configs() {
+        let invalid = |config: WatcherConfig| {
+            config.validate().map_err(|e| e.kind()) == Err(io::ErrorKind::InvalidInput)
+        };
+        let default = WatcherConfig::default;
+        assert!(default().validate().is_ok());
+        assert!(invalid(WatcherConfig {
+            high: 5,
+            low: 10,
+            ..default()
+        }));
+        assert!(invalid(WatcherConfig {
+            high: 5,
+            low: 5,
+            ..default()
+        }));
+        assert!(invalid(WatcherConfig {
+            high: 16,
+            low: 0,
+            ..default()
+        }));
+        assert!(invalid(WatcherConfig {
+            interval: Duration::ZERO,
+            ..default()
+        }));
+        assert!(invalid(WatcherConfig {
+            debounce: TICK / 2,
+            ..default()
+        }));
+        assert!(WatcherConfig {
+            debounce: TICK,
+            ..default()
+        }
+        .validate()
+        .is_ok());
+    }
+}
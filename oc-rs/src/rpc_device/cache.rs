@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::rpc_device::{RPCDevice, RPCDeviceDescriptor};
+
+/// The name under which [`RPCBus::methods`](crate::device_bus::RPCBus::methods) is cached, give
+/// it a ttl with [`CallCache::set_ttl`] like any other method.
+pub const METHODS: &str = "methods";
+
+/// Ttls for calls whose results rarely change, used by [`CallCache::with_defaults`].
+pub const DEFAULT_TTLS: &[(&str, Duration)] = &[
+    ("getItemSlotCount", Duration::from_secs(30)),
+    ("getItemSlotLimit", Duration::from_secs(30)),
+    ("getMaxEnergyStored", Duration::from_secs(30)),
+    (METHODS, Duration::from_secs(300)),
+];
+
+#[derive(Debug)]
+struct CacheEntry {
+    expires: Instant,
+    response: Value,
+}
+
+/// Caches the raw responses of read only calls for a per method time to live, methods without a
+/// ttl are never cached.
+///
+/// Entries are keyed by device, method and parameters and are all dropped as soon as the bus sees
+/// a different device list.
+#[derive(Debug, Default)]
+pub struct CallCache {
+    ttls: HashMap<String, Duration>,
+    entries: HashMap<RPCDevice, HashMap<String, HashMap<String, CacheEntry>>>,
+    devices: Option<Vec<(RPCDevice, Vec<String>)>>,
+}
+
+impl CallCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache with [`DEFAULT_TTLS`] already set.
+    pub fn with_defaults() -> Self {
+        let mut cache = Self::new();
+        for (method, ttl) in DEFAULT_TTLS {
+            cache.set_ttl(method, *ttl);
+        }
+        cache
+    }
+
+    pub fn set_ttl(&mut self, method: &str, ttl: Duration) {
+        self.ttls.insert(method.to_string(), ttl);
+    }
+
+    /// Stops caching `method` and drops everything cached for it.
+    pub fn remove_ttl(&mut self, method: &str) {
+        self.ttls.remove(method);
+        for methods in self.entries.values_mut() {
+            methods.remove(method);
+        }
+    }
+
+    pub fn ttl(&self, method: &str) -> Option<Duration> {
+        self.ttls.get(method).copied()
+    }
+
+    pub fn get(&mut self, device: RPCDevice, method: &str, key: &str) -> Option<Value> {
+        let calls = self.entries.get_mut(&device)?.get_mut(method)?;
+        match calls.get(key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.response.clone()),
+            Some(_) => {
+                calls.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Stores `response` if `method` has a ttl.
+    pub fn insert(&mut self, device: RPCDevice, method: &str, key: String, response: Value) {
+        if let Some(ttl) = self.ttl(method) {
+            self.entries
+                .entry(device)
+                .or_default()
+                .entry(method.to_string())
+                .or_default()
+                .insert(
+                    key,
+                    CacheEntry {
+                        expires: Instant::now() + ttl,
+                        response,
+                    },
+                );
+        }
+    }
+
+    pub fn invalidate_device(&mut self, device: RPCDevice) {
+        self.entries.remove(&device);
+    }
+
+    pub fn invalidate_method(&mut self, device: RPCDevice, method: &str) {
+        if let Some(methods) = self.entries.get_mut(&device) {
+            methods.remove(method);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Called with every device list the bus reads, clears the cache if the list differs from the
+    /// previous one.
+    pub fn observe_devices(&mut self, devices: &[RPCDeviceDescriptor]) {
+        let mut current: Vec<(RPCDevice, Vec<String>)> = devices
+            .iter()
+            .map(|d| {
+                let mut components = d.components.clone();
+                components.sort();
+                (d.device_id, components)
+            })
+            .collect();
+        current.sort();
+
+        if self.devices.as_ref() != Some(&current) {
+            if self.devices.is_some() {
+                self.clear();
+            }
+            self.devices = Some(current);
+        }
+    }
+}
@@ -1,7 +1,6 @@
 use std::io;
 use std::sync::Mutex;
-use serde_json::{Number, Value};
-use crate::device_bus::{BusCall, BusReturn, RPCBus};
+use crate::device_bus::{BusReturn, RPCBus, NO_PARAMS};
 use crate::rpc_device::RPCDevice;
 use crate::util::Item;
 
@@ -13,12 +12,7 @@ pub struct Computer {
 impl Computer {
     pub fn get_item_slot_count(&mut self) -> io::Result<usize> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "getItemSlotCount".to_string(),
-            parameters: vec![],
-        })?;
-        let result: BusReturn<usize> = bus.read()?;
+        let result: BusReturn<usize> = bus.invoke(self.device, "getItemSlotCount", NO_PARAMS)?;
         if let BusReturn::Result(v) = result {
             return Ok(v);
         } else if let BusReturn::Error(e) = result {
@@ -30,12 +24,7 @@ impl Computer {
 
     pub fn get_item_stack_in_slot(&mut self, slot: usize) -> io::Result<Item> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "getItemStackInSlot".to_string(),
-            parameters: vec![Value::Number(Number::from(slot))],
-        })?;
-        let result: BusReturn<Item> = bus.read()?;
+        let result: BusReturn<Item> = bus.invoke(self.device, "getItemStackInSlot", (slot,))?;
         if let BusReturn::Result(v) = result {
             return Ok(v);
         } else if let BusReturn::Error(e) = result {
@@ -46,12 +35,7 @@ impl Computer {
 
     pub fn get_item_slot_limit(&mut self, slot: usize) -> io::Result<usize> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "getItemSlotLimit".to_string(),
-            parameters: vec![Value::Number(Number::from(slot))],
-        })?;
-        let result: BusReturn<usize> = bus.read()?;
+        let result: BusReturn<usize> = bus.invoke(self.device, "getItemSlotLimit", (slot,))?;
         if let BusReturn::Result(v) = result {
             return Ok(v);
         } else if let BusReturn::Error(e) = result {
@@ -62,12 +46,7 @@ impl Computer {
 
     pub fn get_energy_stored(&mut self) -> io::Result<usize> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "getEnergyStored".to_string(),
-            parameters: vec![],
-        })?;
-        let result: BusReturn<usize> = bus.read()?;
+        let result: BusReturn<usize> = bus.invoke(self.device, "getEnergyStored", NO_PARAMS)?;
         if let BusReturn::Result(v) = result {
             return Ok(v);
         } else if let BusReturn::Error(e) = result {
@@ -78,12 +57,7 @@ impl Computer {
 
     pub fn get_max_energy_stored(&mut self) -> io::Result<usize> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "getMaxEnergyStored".to_string(),
-            parameters: vec![],
-        })?;
-        let result: BusReturn<usize> = bus.read()?;
+        let result: BusReturn<usize> = bus.invoke(self.device, "getMaxEnergyStored", NO_PARAMS)?;
         if let BusReturn::Result(v) = result {
             return Ok(v);
         } else if let BusReturn::Error(e) = result {
@@ -94,12 +68,7 @@ impl Computer {
 
     pub fn can_extract_energy(&mut self) -> io::Result<bool> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "canExtractEnergy".to_string(),
-            parameters: vec![],
-        })?;
-        let result: BusReturn<bool> = bus.read()?;
+        let result: BusReturn<bool> = bus.invoke(self.device, "canExtractEnergy", NO_PARAMS)?;
         if let BusReturn::Result(v) = result {
             return Ok(v);
         } else if let BusReturn::Error(e) = result {
@@ -109,12 +78,7 @@ impl Computer {
     }
     pub fn can_receive_energy(&mut self) -> io::Result<bool> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "canReceiveEnergy".to_string(),
-            parameters: vec![],
-        })?;
-        let result: BusReturn<bool> = bus.read()?;
+        let result: BusReturn<bool> = bus.invoke(self.device, "canReceiveEnergy", NO_PARAMS)?;
         if let BusReturn::Result(v) = result {
             return Ok(v);
         } else if let BusReturn::Error(e) = result {
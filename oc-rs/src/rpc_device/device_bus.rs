@@ -1,11 +1,22 @@
 use epoll_rs::Epoll;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::fs::{File, OpenOptions};
 use std::io;
 use std::io::{Read, Write};
+use std::ops::Range;
+use std::time::Duration;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
 
+#[cfg(feature = "cache")]
+use crate::rpc_device::cache::{CallCache, METHODS};
 use crate::rpc_device::device_bus::os_stuff::setup_termios;
+#[cfg(feature = "metrics")]
+use crate::rpc_device::metrics::{BusMetrics, CallOutcome, MetricsSnapshot};
+#[cfg(feature = "throttle")]
+use crate::rpc_device::throttle::Throttle;
 use crate::rpc_device::{RPCDevice, RPCDeviceDescriptor, RPCDeviceMethod};
 
 #[allow(dead_code)]
@@ -13,24 +24,47 @@ use crate::rpc_device::{RPCDevice, RPCDeviceDescriptor, RPCDeviceMethod};
 pub struct RPCBus {
     file: File,
     poll: Epoll,
+    timeout: Option<Duration>,
+    #[cfg(feature = "metrics")]
+    metrics: Option<BusMetrics>,
+    #[cfg(feature = "cache")]
+    cache: Option<CallCache>,
+    #[cfg(feature = "throttle")]
+    throttle: Option<Throttle>,
+    /// Reused for every outgoing frame, so writes don't allocate once it has grown large enough.
+    out_buf: Vec<u8>,
+    /// Incoming data, `in_pos` is where the next frame starts.
+    in_buf: Vec<u8>,
+    in_pos: usize,
+    /// Responses of calls that timed out, still to arrive and be thrown away.
+    stale: usize,
 }
 
-#[derive(Serialize, Deserialize)]
+/// A call on the bus, `P` is anything that serializes to a json array, like a tuple, a slice or
+/// [`NO_PARAMS`].
+///
+/// Calls can be deserialized too, as `BusCall<Vec<serde_json::Value>>` to accept any parameters.
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "type", content = "data")]
-pub enum BusCall {
+pub enum BusCall<'a, P = NoParams> {
     List,
     Methods(RPCDevice),
     #[serde(rename_all = "camelCase")]
     Invoke {
         device_id: RPCDevice,
         // hyphenated
-        #[serde(rename = "name")]
-        method_name: String,
-        parameters: Vec<serde_json::Value>,
+        #[serde(rename = "name", borrow)]
+        method_name: Cow<'a, str>,
+        parameters: P,
     },
 }
 
+pub type NoParams = [u8; 0];
+
+/// The parameters for methods that take none, serializes to `[]`.
+pub const NO_PARAMS: NoParams = [];
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "type", content = "data")]
@@ -41,32 +75,242 @@ pub enum BusReturn<T> {
     Result(#[serde(default)] T), // returned values
 }
 
+/// Implemented by everything a response can be read into, lets the bus tell errors apart without
+/// knowing the exact response type.
+#[cfg_attr(not(any(feature = "metrics", feature = "cache")), allow(dead_code))]
+pub(crate) trait Response {
+    fn is_error(&self) -> bool;
+}
+
+impl<T> Response for BusReturn<T> {
+    fn is_error(&self) -> bool {
+        matches!(self, BusReturn::Error(_))
+    }
+}
+
+impl Response for serde_json::Value {
+    fn is_error(&self) -> bool {
+        self.get("type").and_then(serde_json::Value::as_str) == Some("error")
+    }
+}
+
 pub const DELIMITER: &[u8] = b"\0";
 
+/// How much is read from the console at once.
+const READ_CHUNK: usize = 4096;
+
 impl RPCBus {
     pub fn init(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)?;
+        unsafe { setup_termios(&file) }?;
+        Self::from_file(file)
+    }
+
+    /// Talks over an already set up console, or anything else that can be polled.
+    pub(crate) fn from_file(file: File) -> io::Result<Self> {
         let poll = Epoll::new()?;
-        let file: File = poll
-            .add(
-                OpenOptions::new()
-                    .read(true)
-                    .write(true)
-                    .create(true)
-                    .open(path)?,
-                epoll_rs::Opts::IN,
-            )?
-            .into_file();
+        let file: File = poll.add(file, epoll_rs::Opts::IN)?.into_file();
+
+        Ok(Self {
+            poll,
+            file,
+            timeout: None,
+            #[cfg(feature = "metrics")]
+            metrics: None,
+            #[cfg(feature = "cache")]
+            cache: None,
+            #[cfg(feature = "throttle")]
+            throttle: None,
+            out_buf: Vec::new(),
+            in_buf: Vec::new(),
+            in_pos: 0,
+            stale: 0,
+        })
+    }
 
-        unsafe { setup_termios(&file) }?;
+    /// Sets how long a read waits for the other side to answer before failing with
+    /// [`io::ErrorKind::TimedOut`], `None` (the default) waits forever.
+    ///
+    /// A response arriving after its call timed out is thrown away by the next read, so every call
+    /// still gets its own response.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Starts counting calls, errors, timeouts and latencies for every [`RPCBus::invoke`], does
+    /// nothing if metrics are already enabled.
+    #[cfg(feature = "metrics")]
+    pub fn enable_metrics(&mut self) {
+        self.metrics.get_or_insert_with(BusMetrics::default);
+    }
+
+    /// Stops collecting metrics and throws away everything collected so far.
+    #[cfg(feature = "metrics")]
+    pub fn disable_metrics(&mut self) {
+        self.metrics = None;
+    }
+
+    #[cfg(feature = "metrics")]
+    pub fn reset_metrics(&mut self) {
+        if let Some(metrics) = &mut self.metrics {
+            metrics.reset();
+        }
+    }
+
+    /// Returns a copy of the metrics collected so far, `None` if metrics are not enabled.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_snapshot(&self) -> Option<MetricsSnapshot> {
+        self.metrics.as_ref().map(BusMetrics::snapshot)
+    }
+
+    /// Starts caching responses, only methods that have a ttl set in `cache` are cached.
+    #[cfg(feature = "cache")]
+    pub fn enable_cache(&mut self, cache: CallCache) {
+        self.cache = Some(cache);
+    }
+
+    #[cfg(feature = "cache")]
+    pub fn disable_cache(&mut self) {
+        self.cache = None;
+    }
+
+    #[cfg(feature = "cache")]
+    pub fn cache(&self) -> Option<&CallCache> {
+        self.cache.as_ref()
+    }
+
+    /// Gives access to the cache for changing ttls or invalidating entries.
+    #[cfg(feature = "cache")]
+    pub fn cache_mut(&mut self) -> Option<&mut CallCache> {
+        self.cache.as_mut()
+    }
+
+    /// Rate limits every invoke that is not answered from the cache.
+    #[cfg(feature = "throttle")]
+    pub fn enable_throttle(&mut self, throttle: Throttle) {
+        self.throttle = Some(throttle);
+    }
+
+    #[cfg(feature = "throttle")]
+    pub fn disable_throttle(&mut self) {
+        self.throttle = None;
+    }
+
+    #[cfg(feature = "throttle")]
+    pub fn throttle_mut(&mut self) -> Option<&mut Throttle> {
+        self.throttle.as_mut()
+    }
+
+    /// Calls `method` on `device` and reads back the response, `parameters` has to serialize to a
+    /// json array, use a tuple like `(side, power)` or [`NO_PARAMS`].
+    ///
+    /// Methods without a return value still get a response from the other side, those should be
+    /// invoked with `R` set to something that accepts a missing value, like
+    /// `Option<serde::de::IgnoredAny>`.
+    pub fn invoke<P: Serialize, R>(
+        &mut self,
+        device: RPCDevice,
+        method: &str,
+        parameters: P,
+    ) -> io::Result<BusReturn<R>>
+    where
+        BusReturn<R>: DeserializeOwned,
+    {
+        #[cfg(feature = "cache")]
+        if let Some(key) = self.cache_key(method, &parameters)? {
+            return self.cached(device, method, key, |bus| {
+                bus.call(device, method, parameters)
+            });
+        }
+
+        self.call(device, method, parameters)
+    }
+
+    fn call<P: Serialize, D: DeserializeOwned + Response>(
+        &mut self,
+        device: RPCDevice,
+        method: &str,
+        parameters: P,
+    ) -> io::Result<D> {
+        #[cfg(feature = "throttle")]
+        if let Some(throttle) = &mut self.throttle {
+            throttle.acquire(device, method)?;
+        }
+
+        #[cfg(feature = "metrics")]
+        let start = Instant::now();
+
+        let result = self
+            .write(&BusCall::Invoke {
+                device_id: device,
+                method_name: Cow::Borrowed(method),
+                parameters,
+            })
+            .and_then(|_| self.read());
+
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &mut self.metrics {
+            metrics.record(device, method, start.elapsed(), CallOutcome::of(&result));
+        }
+
+        result
+    }
+
+    /// The key `parameters` are cached under, `None` if `method` is not cached at all.
+    #[cfg(feature = "cache")]
+    fn cache_key<P: Serialize>(&self, method: &str, parameters: &P) -> io::Result<Option<String>> {
+        match &self.cache {
+            Some(cache) if cache.ttl(method).is_some() => {
+                Ok(Some(serde_json::to_string(parameters)?))
+            }
+            _ => Ok(None),
+        }
+    }
 
-        Ok(Self { poll, file })
+    /// Answers from the cache if possible, otherwise `fetch`es the raw response and caches it
+    /// unless it is an error.
+    #[cfg(feature = "cache")]
+    fn cached<D: DeserializeOwned>(
+        &mut self,
+        device: RPCDevice,
+        method: &str,
+        key: String,
+        fetch: impl FnOnce(&mut Self) -> io::Result<serde_json::Value>,
+    ) -> io::Result<D> {
+        let hit = self
+            .cache
+            .as_mut()
+            .and_then(|cache| cache.get(device, method, &key));
+        let response = match hit {
+            Some(response) => response,
+            None => {
+                let response = fetch(self)?;
+                if let (false, Some(cache)) = (response.is_error(), &mut self.cache) {
+                    cache.insert(device, method, key, response.clone());
+                }
+                response
+            }
+        };
+        Ok(serde_json::from_value(response)?)
     }
 
     pub fn list(&mut self) -> io::Result<Vec<RPCDeviceDescriptor>> {
-        self.write(&BusCall::List)?;
+        self.write(&BusCall::<NoParams>::List)?;
 
         let list: BusReturn<bool> = self.read()?;
         if let BusReturn::List(devices) = list {
+            #[cfg(feature = "cache")]
+            if let Some(cache) = &mut self.cache {
+                cache.observe_devices(&devices);
+            }
             Ok(devices)
         } else {
             Err(io::ErrorKind::InvalidData.into())
@@ -74,8 +318,22 @@ impl RPCBus {
     }
 
     pub fn methods(&mut self, device: RPCDevice) -> io::Result<Vec<RPCDeviceMethod>> {
-        self.write(&BusCall::Methods(device))?;
-        let list: BusReturn<bool> = self.read()?;
+        #[cfg(feature = "cache")]
+        let list: BusReturn<bool> = match self.cache_key(METHODS, &NO_PARAMS)? {
+            Some(key) => self.cached(device, METHODS, key, |bus| {
+                bus.write(&BusCall::<NoParams>::Methods(device))?;
+                bus.read()
+            })?,
+            None => {
+                self.write(&BusCall::<NoParams>::Methods(device))?;
+                self.read()?
+            }
+        };
+        #[cfg(not(feature = "cache"))]
+        let list: BusReturn<bool> = {
+            self.write(&BusCall::<NoParams>::Methods(device))?;
+            self.read()?
+        };
         if let BusReturn::Methods(methods) = list {
             Ok(methods)
         } else {
@@ -96,100 +354,235 @@ impl RPCBus {
         Err(io::ErrorKind::NotFound.into())
     }
 
+    /// Writes `data` as a single frame, both delimiters included, with a single `write_all`.
     pub fn write<D: Serialize>(&mut self, data: &D) -> io::Result<()> {
-        self.file.write_all(DELIMITER)?;
-        serde_json::to_writer(&self.file, data)
+        self.out_buf.clear();
+        self.out_buf.extend_from_slice(DELIMITER);
+        serde_json::to_writer(&mut self.out_buf, data)
             .map_err::<io::Error, _>(|_| io::ErrorKind::InvalidData.into())?;
-        self.file.write_all(DELIMITER)?;
+        self.out_buf.extend_from_slice(DELIMITER);
 
-        self.file.flush()?;
+        self.file.write_all(&self.out_buf)
+    }
+
+    fn wait(&self) -> io::Result<()> {
+        match self.timeout {
+            Some(timeout) => match self.poll.wait_one_timeout(timeout)? {
+                Some(_) => Ok(()),
+                None => Err(io::ErrorKind::TimedOut.into()),
+            },
+            None => self.poll.wait_one().map(|_| ()),
+        }
+    }
 
+    /// Reads whatever the other side has sent so far into `in_buf`, waiting for data first.
+    fn fill(&mut self) -> io::Result<()> {
+        self.wait()?;
+
+        let len = self.in_buf.len();
+        self.in_buf.resize(len + READ_CHUNK, 0);
+        let read = match self.file.read(&mut self.in_buf[len..]) {
+            Ok(read) => read,
+            Err(e) => {
+                self.in_buf.truncate(len);
+                return Err(e);
+            }
+        };
+        self.in_buf.truncate(len + read);
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
         Ok(())
     }
 
-    pub fn read<D: DeserializeOwned>(&mut self) -> io::Result<D> {
-        self.poll.wait_one()?;
+    /// Reads the next response, skipping the late responses of calls that timed out first.
+    fn read_frame(&mut self) -> io::Result<Range<usize>> {
+        while self.stale > 0 {
+            self.next_frame().inspect_err(|e| self.count_timeout(e))?;
+            self.stale -= 1;
+        }
+        self.next_frame().inspect_err(|e| self.count_timeout(e))
+    }
+
+    /// The response to a call that timed out is still on its way.
+    fn count_timeout(&mut self, e: &io::Error) {
+        if e.kind() == io::ErrorKind::TimedOut {
+            self.stale += 1;
+        }
+    }
+
+    /// Reads the next frame, returning where its content (without delimiters) is in `in_buf`.
+    ///
+    /// Data is read in chunks, anything read past the end of the frame is kept for the next one.
+    fn next_frame(&mut self) -> io::Result<Range<usize>> {
+        if self.in_pos >= self.in_buf.len() {
+            self.in_buf.clear();
+        } else {
+            self.in_buf.drain(..self.in_pos);
+        }
+        self.in_pos = 0;
 
-        let mut delim_buf = [0; DELIMITER.len()];
-        let read = self.file.read(&mut delim_buf)?;
-        if read != DELIMITER.len() || delim_buf != DELIMITER {
+        while self.in_buf.len() < DELIMITER.len() {
+            self.fill()?;
+        }
+        if !self.in_buf.starts_with(DELIMITER) {
             return Err(io::ErrorKind::UnexpectedEof.into());
         }
 
-        let mut deserializer = serde_json::Deserializer::from_reader(&mut self.file);
+        let start = DELIMITER.len();
+        let mut searched = start;
+        loop {
+            if let Some(i) = self.in_buf[searched..]
+                .windows(DELIMITER.len())
+                .position(|w| w == DELIMITER)
+            {
+                let end = searched + i;
+                self.in_pos = end + DELIMITER.len();
+                return Ok(start..end);
+            }
+            searched = (self.in_buf.len() + 1)
+                .saturating_sub(DELIMITER.len())
+                .max(start);
+            self.fill()?;
+        }
+    }
+
+    pub fn read<D: DeserializeOwned>(&mut self) -> io::Result<D> {
+        let frame = self.read_frame()?;
         // currently errors (missing field data, l1 c17) (Sometimes)
         // This seems to be a result of the other side sending `{"type": "result"}` instead of
         // `{"type": "result", "data": D}`.
-        let data = D::deserialize(&mut deserializer)?;
-
-        let mut delim_buf = [0; DELIMITER.len()];
-        let read = self.file.read(&mut delim_buf)?;
-        if read != DELIMITER.len() || delim_buf != DELIMITER {
-            return Err(io::ErrorKind::UnexpectedEof.into());
-        }
-        Ok(data)
+        Ok(serde_json::from_slice(&self.in_buf[frame])?)
     }
 
     pub fn read_debug<D: DeserializeOwned>(&mut self) -> io::Result<D> {
-        self.poll.wait_one()?;
-
-        let mut delim_buf = [0; DELIMITER.len()];
-        let read = self.file.read(&mut delim_buf)?;
-        if read != DELIMITER.len() || delim_buf != DELIMITER {
-            return Err(io::ErrorKind::UnexpectedEof.into());
-        }
+        let frame = self.read_frame()?;
 
+        println!("debug");
+        print!("{}", String::from_utf8_lossy(&self.in_buf[frame.clone()]));
         println!("debug");
 
-        let mut buf: [u8; 1] = [0; DELIMITER.len()];
-        let _ = self.file.read(&mut buf)?;
-        while buf != DELIMITER {
-            print!("{}", String::from_utf8_lossy(&buf));
-            let _ = self.file.read(&mut buf)?;
-        }
+        Ok(serde_json::from_slice(&self.in_buf[frame])?)
+    }
 
-        println!("debug");
+    pub fn read_test(&mut self) -> io::Result<()> {
+        let frame = self.read_frame()?;
+        print!("{}", String::from_utf8_lossy(&self.in_buf[frame]));
+        Ok(())
+    }
+}
 
-        let mut deserializer = serde_json::Deserializer::from_reader(&mut self.file);
-        //currently errors (missing field data, l1 c17)
-        let data = D::deserialize(&mut deserializer)?;
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::os::fd::OwnedFd;
+    use std::os::unix::net::UnixStream;
 
-        let mut delim_buf = [0; DELIMITER.len()];
-        let read = self.file.read(&mut delim_buf)?;
-        if read != DELIMITER.len() || delim_buf != DELIMITER {
-            return Err(io::ErrorKind::UnexpectedEof.into());
-        }
-        Ok(data)
+    use super::*;
+
+    /// A bus talking to the returned socket instead of a console.
+    pub(crate) fn bus() -> (RPCBus, UnixStream) {
+        let (ours, theirs) = UnixStream::pair().unwrap();
+        let mut bus = RPCBus::from_file(File::from(OwnedFd::from(ours))).unwrap();
+        bus.set_timeout(Some(Duration::from_millis(50)));
+        (bus, theirs)
     }
 
-    pub fn read_test(&mut self) -> io::Result<()> {
-        self.poll.wait_one()?;
+    /// Sends `response` as a frame from the other side.
+    pub(crate) fn send(other: &mut UnixStream, response: &str) {
+        other
+            .write_all(format!("\0{}\0", response).as_bytes())
+            .unwrap();
+    }
 
-        let mut delim_buf = [0; DELIMITER.len()];
-        let read = self.file.read(&mut delim_buf)?;
-        if read != DELIMITER.len() || delim_buf != DELIMITER {
-            return Err(io::ErrorKind::UnexpectedEof.into());
+    /// Everything the bus sent so far.
+    pub(crate) fn sent(other: &mut UnixStream) -> String {
+        other.set_nonblocking(true).unwrap();
+        let mut data = Vec::new();
+        let _ = other.read_to_end(&mut data);
+        other.set_nonblocking(false).unwrap();
+        String::from_utf8_lossy(&data).into_owned()
+    }
+
+    fn respond(other: &mut UnixStream, value: i32) {
+        send(
+            other,
+            &format!("{{\"type\":\"result\",\"data\":{}}}", value),
+        );
+    }
+
+    fn call(bus: &mut RPCBus) -> io::Result<i32> {
+        bus.write(&BusCall::<NoParams>::List)?;
+        match bus.read::<BusReturn<i32>>()? {
+            BusReturn::Result(v) => Ok(v),
+            _ => Err(io::ErrorKind::InvalidData.into()),
         }
+    }
+
+    #[test]
+    fn late_response_is_skipped() {
+        let (mut bus, mut other) = bus();
+        assert_eq!(call(&mut bus).unwrap_err().kind(), io::ErrorKind::TimedOut);
+        respond(&mut other, 1);
+        respond(&mut other, 2);
+        assert_eq!(call(&mut bus).unwrap(), 2);
+        respond(&mut other, 3);
+        assert_eq!(call(&mut bus).unwrap(), 3);
+    }
 
-        // let mut deserializer = serde_json::Deserializer::from_reader(&mut self.file);
-        // //currently errors (missing field data, l1 c17)
-        // let result = D::deserialize(&mut deserializer);
-        //
-        // if let Err(e) = result {
-        //     eprintln!("reading file failed");
-        // }
+    #[test]
+    fn late_responses_of_several_timeouts() {
+        let (mut bus, mut other) = bus();
+        assert_eq!(call(&mut bus).unwrap_err().kind(), io::ErrorKind::TimedOut);
+        // The second call times out while still waiting for the first response.
+        assert_eq!(call(&mut bus).unwrap_err().kind(), io::ErrorKind::TimedOut);
+        respond(&mut other, 1);
+        respond(&mut other, 2);
+        respond(&mut other, 3);
+        assert_eq!(call(&mut bus).unwrap(), 3);
+    }
 
-        let mut delim_buf = [128; DELIMITER.len()];
+    #[test]
+    fn response_split_over_reads() {
+        let (mut bus, mut other) = bus();
+        other.write_all(b"\0{\"type\":\"res").unwrap();
+        assert_eq!(call(&mut bus).unwrap_err().kind(), io::ErrorKind::TimedOut);
+        other.write_all(b"ult\",\"data\":1}\0").unwrap();
+        respond(&mut other, 2);
+        assert_eq!(call(&mut bus).unwrap(), 2);
+    }
 
-        while delim_buf != DELIMITER {
-            let _read = self.file.read(&mut delim_buf)?;
-            print!("{}", String::from_utf8_lossy(&delim_buf))
+    #[test]
+    fn calls_deserialize() {
+        let json = serde_json::to_string(&BusCall::Invoke {
+            device_id: RPCDevice::default(),
+            method_name: Cow::Borrowed("setRedstoneOutput"),
+            parameters: ("north", 15),
+        })
+        .unwrap();
+        match serde_json::from_str::<BusCall<Vec<serde_json::Value>>>(&json).unwrap() {
+            BusCall::Invoke {
+                device_id,
+                method_name,
+                parameters,
+            } => {
+                assert_eq!(device_id, RPCDevice::default());
+                assert_eq!(method_name, "setRedstoneOutput");
+                assert_eq!(
+                    parameters,
+                    [serde_json::json!("north"), serde_json::json!(15)]
+                );
+            }
+            call => panic!("{:?}", call),
         }
 
-        if read != DELIMITER.len() || delim_buf != DELIMITER {
-            return Err(io::ErrorKind::UnexpectedEof.into());
-        }
-        Ok(())
+        // An escaped name can't be borrowed from the input.
+        let json = json.replace("setRedstoneOutput", "a\\\"b");
+        let call: BusCall<Vec<serde_json::Value>> = serde_json::from_str(&json).unwrap();
+        assert!(matches!(call, BusCall::Invoke { method_name, .. } if method_name == "a\"b"));
+        assert!(matches!(
+            serde_json::from_str::<BusCall>(r#"{"type":"list"}"#).unwrap(),
+            BusCall::List
+        ));
     }
 }
 
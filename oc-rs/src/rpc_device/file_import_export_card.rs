@@ -1,62 +1,99 @@
 use anyhow::anyhow;
-use serde_json::Value;
+use serde::de::IgnoredAny;
 
-use crate::device_bus::{BusCall, BusReturn, RPCBus};
+use crate::device_bus::{BusReturn, RPCBus, NO_PARAMS};
 use crate::rpc_device::RPCDevice;
-use crate::util::ImportedFileInfo;
+use crate::util::{ByteArray, ByteBuf, ImportedFileInfo};
 
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
 pub struct FileImportExportCard {
     pub device: RPCDevice,
     pub bus: Mutex<RPCBus>,
 }
 
+/// The raw protocol methods are only used through the sessions below, which make it impossible
+/// to call them out of order.
 impl FileImportExportCard {
-    pub fn begin_export_file(&mut self, name: String) -> anyhow::Result<()> {
+    /// Starts exporting a file called `name`.
+    pub fn begin_export(&mut self, name: &str) -> anyhow::Result<ExportSession<'_>> {
+        self.begin_export_file(name.to_string())?;
+        Ok(ExportSession {
+            card: self,
+            finished: false,
+        })
+    }
+
+    /// Asks the player for a file to import, fails with [`ImportError::Denied`] if there is
+    /// nobody to ask.
+    pub fn request_import(&mut self) -> anyhow::Result<ImportPending<'_>> {
+        if !self.request_import_file()? {
+            return Err(ImportError::Denied.into());
+        }
+        Ok(ImportPending { card: Some(self) })
+    }
+
+    /// Runs the whole import handshake: requests a file, waits for the player to pick one and
+    /// starts the import, reporting every state change to `on_state`.
+    pub fn import(
+        &mut self,
+        options: ImportOptions,
+        mut on_state: impl FnMut(ImportState),
+    ) -> anyhow::Result<ImportSession<'_>> {
+        match self.request_import() {
+            Ok(pending) => pending.wait(options, on_state),
+            Err(e) => {
+                if let Some(ImportError::Denied) = e.downcast_ref() {
+                    on_state(ImportState::Denied);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    pub(crate) fn begin_export_file(&mut self, name: String) -> anyhow::Result<()> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "beginExportFile".to_string(),
-            parameters: vec![Value::String(name)],
-        })?;
+        let result: BusReturn<Option<IgnoredAny>> =
+            bus.invoke(self.device, "beginExportFile", (name,))?;
+        if let BusReturn::Error(e) = result {
+            eprintln!("an error occurred calling the method: {}", e);
+            return Err(anyhow!(e));
+        }
         Ok(())
     }
 
-    pub fn write_export_file(&mut self, data: Vec<u8>) -> anyhow::Result<()> {
+    pub(crate) fn write_export_file(&mut self, data: &[u8]) -> anyhow::Result<()> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "writeExportFile".to_string(),
-            parameters: vec![Value::Array(
-                data.into_iter().map(|b| Value::Number(b.into())).collect(),
-            )],
-        })?;
+        let result: BusReturn<Option<IgnoredAny>> =
+            bus.invoke(self.device, "writeExportFile", (ByteArray(data),))?;
+        if let BusReturn::Error(e) = result {
+            eprintln!("an error occurred calling the method: {}", e);
+            return Err(anyhow!(e));
+        }
         Ok(())
     }
 
-    pub fn finish_export_file(&mut self) -> anyhow::Result<()> {
+    pub(crate) fn finish_export_file(&mut self) -> anyhow::Result<()> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "finishExportFile".to_string(),
-            parameters: vec![],
-        })?;
+        let result: BusReturn<Option<IgnoredAny>> =
+            bus.invoke(self.device, "finishExportFile", NO_PARAMS)?;
+        if let BusReturn::Error(e) = result {
+            eprintln!("an error occurred calling the method: {}", e);
+            return Err(anyhow!(e));
+        }
         Ok(())
     }
 
-    pub fn request_import_file(&mut self) -> anyhow::Result<()> {
+    /// Returns whether there was a player to ask.
+    pub(crate) fn request_import_file(&mut self) -> anyhow::Result<bool> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "requestImportFile".to_string(),
-            parameters: vec![],
-        })?;
-        let result: BusReturn<bool> = bus.read_debug()?;
-        if let BusReturn::Result(true) = result {
-            Ok(())
-        } else if let BusReturn::Result(false) = result {
-            Err(anyhow!("Request denied"))
+        let result: BusReturn<bool> = bus.invoke(self.device, "requestImportFile", NO_PARAMS)?;
+        if let BusReturn::Result(v) = result {
+            Ok(v)
         } else if let BusReturn::Error(e) = result {
             eprintln!("an error occurred calling the method: {}", e);
             Err(anyhow!(e))
@@ -65,34 +102,28 @@ impl FileImportExportCard {
         }
     }
 
-    pub fn begin_import_file(&mut self) -> anyhow::Result<ImportedFileInfo> {
+    /// Returns `None` while the player has not picked a file yet.
+    pub(crate) fn begin_import_file(&mut self) -> anyhow::Result<Option<ImportedFileInfo>> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "beginImportFile".to_string(),
-            parameters: vec![],
-        })?;
-        let result: BusReturn<ImportedFileInfo> = bus.read()?;
+        let result: BusReturn<Option<ImportedFileInfo>> =
+            bus.invoke(self.device, "beginImportFile", NO_PARAMS)?;
         if let BusReturn::Result(v) = result {
             Ok(v)
         } else if let BusReturn::Error(e) = result {
             eprintln!("an error occurred calling the method: {}", e);
-            Err(anyhow!(e))
+            Err(anyhow!(ImportError::from_message(e)))
         } else {
             Err(anyhow!("Invalid Data"))
         }
     }
 
-    pub fn read_import_file(&mut self) -> anyhow::Result<Vec<u8>> {
+    /// Reads the next chunk of the file being imported, `None` once the whole file has been read.
+    pub(crate) fn read_import_chunk(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "readImportFile".to_string(),
-            parameters: vec![],
-        })?;
-        let result: BusReturn<Vec<u8>> = bus.read()?;
+        let result: BusReturn<Option<ByteBuf>> =
+            bus.invoke(self.device, "readImportFile", NO_PARAMS)?;
         if let BusReturn::Result(v) = result {
-            Ok(v)
+            Ok(v.map(ByteBuf::into_inner))
         } else if let BusReturn::Error(e) = result {
             eprintln!("an error occurred calling the method: {}", e);
             Err(anyhow!(e))
@@ -103,11 +134,210 @@ impl FileImportExportCard {
 
     pub fn reset(&mut self) -> anyhow::Result<()> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "reset".to_string(),
-            parameters: vec![],
-        })?;
+        let result: BusReturn<Option<IgnoredAny>> = bus.invoke(self.device, "reset", NO_PARAMS)?;
+        if let BusReturn::Error(e) = result {
+            eprintln!("an error occurred calling the method: {}", e);
+            return Err(anyhow!(e));
+        }
         Ok(())
     }
 }
+
+/// Where an import handshake is at, see [`FileImportExportCard::import`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportState {
+    /// The player has been asked and has not picked a file yet.
+    Pending,
+    /// The player picked a file, the import has started.
+    Accepted,
+    /// There was no player to ask.
+    Denied,
+    /// The player closed the file dialog.
+    Cancelled,
+    /// The player did not pick a file in time.
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImportOptions {
+    /// How long to wait between two checks whether the player picked a file.
+    pub poll_interval: Duration,
+    /// How long to wait for the player in total, `None` waits forever.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(500),
+            timeout: None,
+        }
+    }
+}
+
+/// Why an import did not start, returned inside the `anyhow::Error` so it can be downcast.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    Denied,
+    Cancelled,
+    TimedOut,
+    /// Any other error the card reported.
+    Card(String),
+}
+
+/// The error `beginImportFile` fails with once the player closed the file dialog without picking
+/// a file. Only this exact message means cancelled, anything else is an [`ImportError::Card`].
+pub const IMPORT_CANCELLED_MESSAGE: &str = "import canceled";
+
+impl ImportError {
+    fn from_message(message: String) -> Self {
+        if message == IMPORT_CANCELLED_MESSAGE {
+            ImportError::Cancelled
+        } else {
+            ImportError::Card(message)
+        }
+    }
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Denied => write!(f, "there is no player to request a file from"),
+            ImportError::Cancelled => write!(f, "the player cancelled the import"),
+            ImportError::TimedOut => write!(f, "the player did not pick a file in time"),
+            ImportError::Card(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+/// An export started by [`FileImportExportCard::begin_export`], dropping it before
+/// [`ExportSession::finish`] resets the card and cancels the export.
+pub struct ExportSession<'a> {
+    card: &'a mut FileImportExportCard,
+    finished: bool,
+}
+
+impl ExportSession<'_> {
+    /// Sends the next part of the file.
+    pub fn write(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        self.card.write_export_file(data)
+    }
+
+    pub fn finish(mut self) -> anyhow::Result<()> {
+        self.card.finish_export_file()?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for ExportSession<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.card.reset();
+        }
+    }
+}
+
+/// An import that has been requested from the player but not started yet, dropping it resets the
+/// card.
+pub struct ImportPending<'a> {
+    // Only `None` after `begin` took it.
+    card: Option<&'a mut FileImportExportCard>,
+}
+
+impl<'a> ImportPending<'a> {
+    /// Waits for the player to pick a file with the default [`ImportOptions`].
+    pub fn begin(self) -> anyhow::Result<ImportSession<'a>> {
+        self.wait(ImportOptions::default(), |_| ())
+    }
+
+    /// Polls the card until the player picked a file, cancelled or `options.timeout` ran out,
+    /// reporting every state change to `on_state`.
+    pub fn wait(
+        mut self,
+        options: ImportOptions,
+        mut on_state: impl FnMut(ImportState),
+    ) -> anyhow::Result<ImportSession<'a>> {
+        let start = Instant::now();
+        on_state(ImportState::Pending);
+        loop {
+            let card = self.card.as_mut().unwrap();
+            match card.begin_import_file() {
+                Ok(Some(info)) => {
+                    on_state(ImportState::Accepted);
+                    return Ok(ImportSession {
+                        card: self.card.take().unwrap(),
+                        info,
+                        eof: false,
+                    });
+                }
+                Ok(None) => (),
+                Err(e) => {
+                    if let Some(ImportError::Cancelled) = e.downcast_ref() {
+                        on_state(ImportState::Cancelled);
+                    }
+                    return Err(e);
+                }
+            }
+
+            if let Some(timeout) = options.timeout {
+                if start.elapsed() >= timeout {
+                    on_state(ImportState::TimedOut);
+                    return Err(ImportError::TimedOut.into());
+                }
+            }
+            thread::sleep(options.poll_interval);
+        }
+    }
+}
+
+impl Drop for ImportPending<'_> {
+    fn drop(&mut self) {
+        if let Some(card) = self.card.take() {
+            let _ = card.reset();
+        }
+    }
+}
+
+/// A running import, dropping it before the whole file was read resets the card.
+pub struct ImportSession<'a> {
+    card: &'a mut FileImportExportCard,
+    info: ImportedFileInfo,
+    eof: bool,
+}
+
+impl ImportSession<'_> {
+    pub fn info(&self) -> &ImportedFileInfo {
+        &self.info
+    }
+
+    /// Reads the next part of the file, `None` once the whole file has been read.
+    pub fn read_chunk(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        if self.eof {
+            return Ok(None);
+        }
+        match self.card.read_import_chunk()? {
+            // An empty chunk is treated as the end as well, so a misbehaving card can't keep a
+            // reader spinning.
+            Some(chunk) if !chunk.is_empty() => Ok(Some(chunk)),
+            _ => {
+                self.eof = true;
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn is_eof(&self) -> bool {
+        self.eof
+    }
+}
+
+impl Drop for ImportSession<'_> {
+    fn drop(&mut self) {
+        if !self.eof {
+            let _ = self.card.reset();
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+#[cfg(feature = "pretty_print")]
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::time::Duration;
+
+use crate::device_bus::Response;
+use crate::rpc_device::RPCDevice;
+
+/// Upper bounds (in microseconds) of the latency histogram buckets, anything slower than the
+/// last bound ends up in an extra overflow bucket.
+pub const BUCKET_BOUNDS_US: [u64; 16] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+
+/// How a single call on the bus ended, as far as the metrics are concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallOutcome {
+    Ok,
+    Error,
+    Timeout,
+}
+
+impl CallOutcome {
+    pub(crate) fn of<D: Response>(result: &io::Result<D>) -> Self {
+        match result {
+            Ok(response) if response.is_error() => CallOutcome::Error,
+            Ok(_) => CallOutcome::Ok,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => CallOutcome::Timeout,
+            Err(_) => CallOutcome::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKET_BOUNDS_US.len() + 1],
+    count: u64,
+    total: Duration,
+    min: Option<Duration>,
+    max: Option<Duration>,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let us = latency.as_micros();
+        let bucket = BUCKET_BOUNDS_US
+            .iter()
+            .position(|b| us <= *b as u128)
+            .unwrap_or(BUCKET_BOUNDS_US.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total += latency;
+        self.min = Some(self.min.map_or(latency, |m| m.min(latency)));
+        self.max = Some(self.max.map_or(latency, |m| m.max(latency)));
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (b, o) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *b += o;
+        }
+        self.count += other.count;
+        self.total += other.total;
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    /// The raw bucket counts, index `i` counts the calls that took at most `BUCKET_BOUNDS_US[i]`
+    /// microseconds, the last entry counts everything slower.
+    pub fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.max
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(Duration::from_nanos(
+            (self.total.as_nanos() / self.count as u128) as u64,
+        ))
+    }
+
+    /// Estimates the given quantile (`0.0..=1.0`) as the upper bound of the bucket it falls in,
+    /// clamped to the slowest recorded call.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let target = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                let bound = BUCKET_BOUNDS_US
+                    .get(i)
+                    .map(|us| Duration::from_micros(*us))
+                    .unwrap_or(Duration::MAX);
+                return self.max.map(|max| max.min(bound));
+            }
+        }
+        self.max
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CallStats {
+    pub calls: u64,
+    pub errors: u64,
+    pub timeouts: u64,
+    pub latency: LatencyHistogram,
+}
+
+impl CallStats {
+    pub fn record(&mut self, latency: Duration, outcome: CallOutcome) {
+        self.calls += 1;
+        match outcome {
+            CallOutcome::Ok => (),
+            CallOutcome::Error => self.errors += 1,
+            CallOutcome::Timeout => self.timeouts += 1,
+        }
+        self.latency.record(latency);
+    }
+
+    pub fn merge(&mut self, other: &CallStats) {
+        self.calls += other.calls;
+        self.errors += other.errors;
+        self.timeouts += other.timeouts;
+        self.latency.merge(&other.latency);
+    }
+}
+
+/// Per device, per method call statistics collected by an [`RPCBus`](crate::device_bus::RPCBus)
+/// with metrics enabled.
+#[derive(Debug, Default)]
+pub struct BusMetrics {
+    devices: HashMap<RPCDevice, HashMap<String, CallStats>>,
+}
+
+impl BusMetrics {
+    pub fn record(
+        &mut self,
+        device: RPCDevice,
+        method: &str,
+        latency: Duration,
+        outcome: CallOutcome,
+    ) {
+        let methods = self.devices.entry(device).or_default();
+        // Only allocate the method name the first time we see it.
+        if let Some(stats) = methods.get_mut(method) {
+            stats.record(latency, outcome);
+        } else {
+            let mut stats = CallStats::default();
+            stats.record(latency, outcome);
+            methods.insert(method.to_string(), stats);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.devices.clear();
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let mut methods: Vec<MethodMetrics> = self
+            .devices
+            .iter()
+            .flat_map(|(device, methods)| {
+                methods.iter().map(|(method, stats)| MethodMetrics {
+                    device: *device,
+                    method: method.clone(),
+                    stats: stats.clone(),
+                })
+            })
+            .collect();
+        methods.sort_by(|a, b| (a.device, &a.method).cmp(&(b.device, &b.method)));
+        MetricsSnapshot { methods }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MethodMetrics {
+    pub device: RPCDevice,
+    pub method: String,
+    pub stats: CallStats,
+}
+
+/// A point in time copy of the collected metrics, sorted by device and method.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsSnapshot {
+    pub methods: Vec<MethodMetrics>,
+}
+
+impl MetricsSnapshot {
+    pub fn get(&self, device: RPCDevice, method: &str) -> Option<&CallStats> {
+        self.methods
+            .iter()
+            .find(|m| m.device == device && m.method == method)
+            .map(|m| &m.stats)
+    }
+
+    pub fn per_device(&self) -> HashMap<RPCDevice, CallStats> {
+        let mut devices: HashMap<RPCDevice, CallStats> = HashMap::new();
+        for m in &self.methods {
+            devices.entry(m.device).or_default().merge(&m.stats);
+        }
+        devices
+    }
+
+    pub fn per_method(&self) -> HashMap<String, CallStats> {
+        let mut methods: HashMap<String, CallStats> = HashMap::new();
+        for m in &self.methods {
+            methods.entry(m.method.clone()).or_default().merge(&m.stats);
+        }
+        methods
+    }
+
+    pub fn total(&self) -> CallStats {
+        let mut total = CallStats::default();
+        for m in &self.methods {
+            total.merge(&m.stats);
+        }
+        total
+    }
+}
+
+#[cfg(feature = "pretty_print")]
+impl Display for CallStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let fmt_opt =
+            |d: Option<Duration>| d.map_or_else(|| "-".to_string(), |d| format!("{:?}", d));
+        writeln!(
+            f,
+            "\tCalls: {} (errors: {}, timeouts: {})",
+            self.calls, self.errors, self.timeouts
+        )?;
+        writeln!(
+            f,
+            "\tLatency: min {} / mean {} / p50 {} / p90 {} / p99 {} / max {}",
+            fmt_opt(self.latency.min()),
+            fmt_opt(self.latency.mean()),
+            fmt_opt(self.latency.quantile(0.5)),
+            fmt_opt(self.latency.quantile(0.9)),
+            fmt_opt(self.latency.quantile(0.99)),
+            fmt_opt(self.latency.max()),
+        )
+    }
+}
+
+#[cfg(feature = "pretty_print")]
+impl Display for MetricsSnapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for m in &self.methods {
+            writeln!(f, "{} {}:", m.device, m.method)?;
+            write!(f, "{}", m.stats)?;
+        }
+        Ok(())
+    }
+}
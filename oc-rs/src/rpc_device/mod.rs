@@ -7,6 +7,12 @@ pub mod sound_card;
 pub mod computer;
 #[cfg(feature = "file_transfer")]
 pub mod file_import_export_card;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg(feature = "throttle")]
+pub mod throttle;
 
 #[cfg(feature = "redstone_interface")]
 pub use redstone_interface::RedstoneInterface;
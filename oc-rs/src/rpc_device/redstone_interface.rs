@@ -1,7 +1,7 @@
-use crate::device_bus::{BusCall, BusReturn, RPCBus};
+use crate::device_bus::{BusReturn, RPCBus};
 use crate::rpc_device::RPCDevice;
 use crate::util::Side;
-use serde_json::{Number, Value};
+use serde::de::IgnoredAny;
 use std::io;
 use std::sync::Mutex;
 
@@ -11,48 +11,61 @@ pub struct RedstoneInterface {
 }
 
 impl RedstoneInterface {
-    pub fn get_redstone_output(&mut self, side: Side) -> io::Result<usize> {
-        let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "getRedstoneOutput".to_string(),
-            parameters: vec![Value::String(side.name().to_string())],
-        })?;
-        let result: BusReturn<usize> = bus.read()?;
-        if let BusReturn::Result(v) = result {
-            return Ok(v);
-        } else if let BusReturn::Error(e) = result {
-            eprintln!("an error occurred calling the method: {}", e);
-        }
-        Err(io::ErrorKind::InvalidData.into())
+    pub fn get_redstone_output(&self, side: Side) -> io::Result<usize> {
+        get_redstone_output(&mut self.bus.lock().unwrap(), self.device, side)
     }
 
-    pub fn get_redstone_input(&mut self, side: Side) -> io::Result<usize> {
-        let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "getRedstoneInput".to_string(),
-            parameters: vec![Value::String(side.name().to_string())],
-        })?;
-        let result: BusReturn<usize> = bus.read()?;
-        if let BusReturn::Result(v) = result {
-            return Ok(v);
-        } else if let BusReturn::Error(e) = result {
-            eprintln!("an error occurred calling the method: {}", e);
-        }
-        Err(io::ErrorKind::InvalidData.into())
+    pub fn get_redstone_input(&self, side: Side) -> io::Result<usize> {
+        get_redstone_input(&mut self.bus.lock().unwrap(), self.device, side)
     }
 
-    pub fn set_redstone_output(&mut self, side: Side, power: usize) -> io::Result<()> {
-        let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "setRedstoneOutput".to_string(),
-            parameters: vec![
-                Value::String(side.name().to_string()),
-                Value::Number(Number::from(power)),
-            ],
-        })?;
-        Ok(())
+    pub fn set_redstone_output(&self, side: Side, power: usize) -> io::Result<()> {
+        set_redstone_output(&mut self.bus.lock().unwrap(), self.device, side, power)
     }
 }
+
+// The calls themselves take the bus, so code driving several interfaces over one bus (like
+// `redstone::signals`) doesn't need an interface per device.
+
+pub(crate) fn get_redstone_output(
+    bus: &mut RPCBus,
+    device: RPCDevice,
+    side: Side,
+) -> io::Result<usize> {
+    let result: BusReturn<usize> = bus.invoke(device, "getRedstoneOutput", (side.name(),))?;
+    if let BusReturn::Result(v) = result {
+        return Ok(v);
+    } else if let BusReturn::Error(e) = result {
+        eprintln!("an error occurred calling the method: {}", e);
+    }
+    Err(io::ErrorKind::InvalidData.into())
+}
+
+pub(crate) fn get_redstone_input(
+    bus: &mut RPCBus,
+    device: RPCDevice,
+    side: Side,
+) -> io::Result<usize> {
+    let result: BusReturn<usize> = bus.invoke(device, "getRedstoneInput", (side.name(),))?;
+    if let BusReturn::Result(v) = result {
+        return Ok(v);
+    } else if let BusReturn::Error(e) = result {
+        eprintln!("an error occurred calling the method: {}", e);
+    }
+    Err(io::ErrorKind::InvalidData.into())
+}
+
+pub(crate) fn set_redstone_output(
+    bus: &mut RPCBus,
+    device: RPCDevice,
+    side: Side,
+    power: usize,
+) -> io::Result<()> {
+    let result: BusReturn<Option<IgnoredAny>> =
+        bus.invoke(device, "setRedstoneOutput", (side.name(), power))?;
+    if let BusReturn::Error(e) = result {
+        eprintln!("an error occurred calling the method: {}", e);
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+    Ok(())
+}
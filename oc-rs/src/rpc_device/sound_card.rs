@@ -1,8 +1,14 @@
+use crate::device_bus::{BusReturn, RPCBus};
+use crate::rpc_device::RPCDevice;
+use crate::util::ResourceLocation;
+use serde::de::IgnoredAny;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::io;
+use std::str::FromStr;
 use std::sync::Mutex;
-use serde_json::Value;
-use crate::device_bus::{BusCall, BusReturn, RPCBus};
-use crate::rpc_device::RPCDevice;
+use std::time::Duration;
 
 pub struct SoundCard {
     pub device: RPCDevice,
@@ -10,14 +16,11 @@ pub struct SoundCard {
 }
 
 impl SoundCard {
-    pub fn find_sound(&mut self, sound: String) -> io::Result<Vec<String>> {
+    /// Looks up the sounds whose name contains `sound`.
+    pub fn find_sound(&mut self, sound: &str) -> io::Result<Vec<ResourceLocation>> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "findSound".to_string(),
-            parameters: vec![Value::String(sound)],
-        })?;
-        let result: BusReturn<Vec<String>> = bus.read()?;
+        let result: BusReturn<Vec<ResourceLocation>> =
+            bus.invoke(self.device, "findSound", (sound,))?;
         if let BusReturn::Result(v) = result {
             return Ok(v);
         } else if let BusReturn::Error(e) = result {
@@ -26,13 +29,358 @@ impl SoundCard {
         Err(io::ErrorKind::InvalidData.into())
     }
 
-    pub fn play_sound (&mut self, sound: String) -> io::Result<()> {
+    /// Plays `sound` at full volume and normal pitch.
+    pub fn play_sound(&mut self, sound: &ResourceLocation) -> io::Result<()> {
+        self.invoke_play((sound,))
+    }
+
+    /// Fails with [`SoundError::Cooldown`] (in an [`io::Error`] of kind
+    /// [`io::ErrorKind::WouldBlock`]) if the card is still cooling down from the last sound.
+    pub fn play_sound_with(
+        &mut self,
+        sound: &ResourceLocation,
+        volume: Volume,
+        pitch: Pitch,
+    ) -> io::Result<()> {
+        self.invoke_play((sound, volume.get(), pitch.get()))
+    }
+
+    pub fn play_note(&mut self, note: &Note, volume: Volume) -> io::Result<()> {
+        self.play_sound_with(&note.sound(), volume, note.pitch())
+    }
+
+    fn invoke_play<P: Serialize>(&mut self, parameters: P) -> io::Result<()> {
         let mut bus = self.bus.lock().unwrap();
-        bus.write(&BusCall::Invoke {
-            device_id: self.device,
-            method_name: "playSound".to_string(),
-            parameters: vec![Value::String(sound)],
-        })?;
-        Ok(())
+        let result: BusReturn<Option<IgnoredAny>> = bus
+            .invoke(self.device, "playSound", parameters)
+            .map_err(throttled_to_cooldown)?;
+        match result {
+            BusReturn::Error(e) if e == SOUND_COOLDOWN_MESSAGE => {
+                Err(SoundError::Cooldown { retry_after: None }.into())
+            }
+            BusReturn::Error(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The error `playSound` fails with while the card is still cooling down from the last sound,
+/// it only allows a sound every few ticks.
+pub const SOUND_COOLDOWN_MESSAGE: &str = "sound card is on cooldown";
+
+/// A client side sound cooldown (see [`Throttle::sound_cooldown`]) is reported like the one of
+/// the card itself.
+///
+/// [`Throttle::sound_cooldown`]: crate::rpc_device::throttle::Throttle::sound_cooldown
+#[cfg(feature = "throttle")]
+fn throttled_to_cooldown(e: io::Error) -> io::Error {
+    use crate::rpc_device::throttle::ThrottledError;
+    match ThrottledError::from_io(&e) {
+        Some(t) => SoundError::Cooldown {
+            retry_after: Some(t.retry_after),
+        }
+        .into(),
+        None => e,
+    }
+}
+
+#[cfg(not(feature = "throttle"))]
+fn throttled_to_cooldown(e: io::Error) -> io::Error {
+    e
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SoundError {
+    /// The card is still cooling down from the last sound, `retry_after` is only known for the
+    /// client side cooldown.
+    Cooldown {
+        retry_after: Option<Duration>,
+    },
+    InvalidVolume(f32),
+    InvalidPitch(f32),
+    InvalidNote(String),
+    UnknownInstrument(String),
+}
+
+impl SoundError {
+    /// Gets the sound error back out of an error returned by the card, if it is one.
+    pub fn from_io(error: &io::Error) -> Option<&SoundError> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+impl Display for SoundError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SoundError::Cooldown {
+                retry_after: Some(d),
+            } => {
+                write!(f, "the sound card is cooling down, retry after {:?}", d)
+            }
+            SoundError::Cooldown { retry_after: None } => {
+                write!(f, "the sound card is cooling down")
+            }
+            SoundError::InvalidVolume(v) => write!(f, "volume {} is not in 0.0..=1.0", v),
+            SoundError::InvalidPitch(p) => write!(f, "pitch {} is not in 0.5..=2.0", p),
+            SoundError::InvalidNote(n) => write!(f, "{:?} is not a note between F#3 and F#5", n),
+            SoundError::UnknownInstrument(i) => write!(f, "unknown instrument {:?}", i),
+        }
+    }
+}
+
+impl Error for SoundError {}
+
+impl From<SoundError> for io::Error {
+    fn from(e: SoundError) -> Self {
+        let kind = match e {
+            SoundError::Cooldown { .. } => io::ErrorKind::WouldBlock,
+            _ => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// A volume in `0.0..=1.0`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Volume(f32);
+
+impl Volume {
+    pub const MAX: Volume = Volume(1.0);
+
+    pub fn new(volume: f32) -> Result<Self, SoundError> {
+        if (0.0..=1.0).contains(&volume) {
+            Ok(Self(volume))
+        } else {
+            Err(SoundError::InvalidVolume(volume))
+        }
+    }
+
+    pub fn get(&self) -> f32 {
+        self.0
+    }
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self::MAX
+    }
+}
+
+/// A playback speed in `0.5..=2.0`, the range minecraft plays sounds at. `2.0` is an octave up.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Pitch(f32);
+
+impl Pitch {
+    pub const NORMAL: Pitch = Pitch(1.0);
+
+    pub fn new(pitch: f32) -> Result<Self, SoundError> {
+        if (0.5..=2.0).contains(&pitch) {
+            Ok(Self(pitch))
+        } else {
+            Err(SoundError::InvalidPitch(pitch))
+        }
+    }
+
+    pub fn get(&self) -> f32 {
+        self.0
+    }
+}
+
+impl Default for Pitch {
+    fn default() -> Self {
+        Self::NORMAL
+    }
+}
+
+/// The sounds a note block can make, named like their `block.note_block.*` sounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instrument {
+    Harp,
+    Basedrum,
+    Snare,
+    Hat,
+    Bass,
+    Flute,
+    Bell,
+    Guitar,
+    Chime,
+    Xylophone,
+    IronXylophone,
+    CowBell,
+    Didgeridoo,
+    Bit,
+    Banjo,
+    Pling,
+}
+
+impl Instrument {
+    pub const ALL: [Instrument; 16] = [
+        Instrument::Harp,
+        Instrument::Basedrum,
+        Instrument::Snare,
+        Instrument::Hat,
+        Instrument::Bass,
+        Instrument::Flute,
+        Instrument::Bell,
+        Instrument::Guitar,
+        Instrument::Chime,
+        Instrument::Xylophone,
+        Instrument::IronXylophone,
+        Instrument::CowBell,
+        Instrument::Didgeridoo,
+        Instrument::Bit,
+        Instrument::Banjo,
+        Instrument::Pling,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instrument::Harp => "harp",
+            Instrument::Basedrum => "basedrum",
+            Instrument::Snare => "snare",
+            Instrument::Hat => "hat",
+            Instrument::Bass => "bass",
+            Instrument::Flute => "flute",
+            Instrument::Bell => "bell",
+            Instrument::Guitar => "guitar",
+            Instrument::Chime => "chime",
+            Instrument::Xylophone => "xylophone",
+            Instrument::IronXylophone => "iron_xylophone",
+            Instrument::CowBell => "cow_bell",
+            Instrument::Didgeridoo => "didgeridoo",
+            Instrument::Bit => "bit",
+            Instrument::Banjo => "banjo",
+            Instrument::Pling => "pling",
+        }
+    }
+
+    pub fn sound(&self) -> ResourceLocation {
+        ResourceLocation::minecraft(&format!("block.note_block.{}", self.name()))
+            .expect("instrument names are valid paths")
+    }
+}
+
+impl FromStr for Instrument {
+    type Err = SoundError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        Instrument::ALL
+            .into_iter()
+            .find(|i| i.name() == s)
+            .ok_or(SoundError::UnknownInstrument(s))
+    }
+}
+
+/// A note as a note block plays it: 25 semitones, from 0 (F#3 on a harp) to 24 (F#5).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Note {
+    pub instrument: Instrument,
+    semitone: u8,
+}
+
+impl Note {
+    /// The highest semitone, as many clicks as a note block goes.
+    pub const MAX_SEMITONE: u8 = 24;
+
+    pub fn new(instrument: Instrument, semitone: u8) -> Result<Self, SoundError> {
+        if semitone > Self::MAX_SEMITONE {
+            return Err(SoundError::InvalidNote(semitone.to_string()));
+        }
+        Ok(Self {
+            instrument,
+            semitone,
+        })
+    }
+
+    /// Parses a note name like `F#4` or `Bb3`, in the harp's range of F#3 to F#5. Other
+    /// instruments play the same semitones in their own octave.
+    pub fn parse(instrument: Instrument, name: &str) -> Result<Self, SoundError> {
+        let invalid = || SoundError::InvalidNote(name.to_string());
+        let mut chars = name.chars();
+        let base = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('C') => 0,
+            Some('D') => 2,
+            Some('E') => 4,
+            Some('F') => 5,
+            Some('G') => 7,
+            Some('A') => 9,
+            Some('B') => 11,
+            _ => return Err(invalid()),
+        };
+        let rest = chars.as_str();
+        let (offset, octave) = match rest.chars().next() {
+            Some('#') => (1, &rest[1..]),
+            Some('b') => (-1, &rest[1..]),
+            _ => (0, rest),
+        };
+        let octave: i32 = octave.parse().map_err(|_| invalid())?;
+        // F#3 is the lowest note.
+        let semitone = octave * 12 + base + offset - (3 * 12 + 6);
+        if !(0..=Self::MAX_SEMITONE as i32).contains(&semitone) {
+            return Err(invalid());
+        }
+        Self::new(instrument, semitone as u8)
+    }
+
+    pub fn semitone(&self) -> u8 {
+        self.semitone
+    }
+
+    pub fn sound(&self) -> ResourceLocation {
+        self.instrument.sound()
+    }
+
+    /// The pitch the note block would play this note at.
+    pub fn pitch(&self) -> Pitch {
+        Pitch(2f32.powf((self.semitone as f32 - 12.0) / 12.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(name: &str) -> Result<u8, SoundError> {
+        Note::parse(Instrument::Harp, name).map(|n| n.semitone())
+    }
+
+    #[test]
+    fn parse_note_names() {
+        assert_eq!(note("F#3"), Ok(0));
+        assert_eq!(note("Gb3"), Ok(0));
+        assert_eq!(note("G3"), Ok(1));
+        assert_eq!(note("Bb3"), Ok(4));
+        assert_eq!(note("C4"), Ok(6));
+        assert_eq!(note("f#4"), Ok(12));
+        assert_eq!(note("F#5"), Ok(24));
+    }
+
+    #[test]
+    fn reject_invalid_notes() {
+        for name in ["G5", "F3", "E#2", "H4", "F#", "#4", "", "C4x"] {
+            assert_eq!(note(name), Err(SoundError::InvalidNote(name.to_string())));
+        }
+        assert!(Note::new(Instrument::Bell, 25).is_err());
+    }
+
+    #[test]
+    fn note_pitch() {
+        let pitch = |semitone| Note::new(Instrument::Harp, semitone).unwrap().pitch().get();
+        assert_eq!(pitch(0), 0.5);
+        assert_eq!(pitch(12), 1.0);
+        assert_eq!(pitch(24), 2.0);
+        assert!((pitch(1) - 0.529732).abs() < 1e-6);
+        assert!((pitch(19) - 1.498307).abs() < 1e-6);
+    }
+
+    #[test]
+    fn note_sound() {
+        let note = Note::parse(Instrument::IronXylophone, "A4").unwrap();
+        assert_eq!(
+            note.sound().to_string(),
+            "minecraft:block.note_block.iron_xylophone"
+        );
+        assert_eq!("COW_BELL".parse(), Ok(Instrument::CowBell));
     }
 }
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::rpc_device::RPCDevice;
+
+/// A classic token bucket, holds up to `capacity` tokens and gains `per_second` tokens every
+/// second, every call takes one token.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    per_second: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+/// Rejects a limit no call could ever get past, rather than hanging the first throttled call.
+fn check_limit(capacity: u32, per_second: f64) -> io::Result<()> {
+    if capacity < 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a token bucket needs a capacity of at least 1",
+        ));
+    }
+    if !(per_second.is_finite() && per_second > 0.0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "a token bucket needs a finite, positive refill rate, got {}",
+                per_second
+            ),
+        ));
+    }
+    Ok(())
+}
+
+impl TokenBucket {
+    /// A full bucket, so the first `capacity` calls go through right away. Fails with
+    /// [`io::ErrorKind::InvalidInput`] if `capacity` is 0 or `per_second` isn't a finite number
+    /// above 0.
+    pub fn new(capacity: u32, per_second: f64) -> io::Result<Self> {
+        check_limit(capacity, per_second)?;
+        Ok(Self::full(capacity, per_second))
+    }
+
+    /// Only for limits that have been checked already.
+    fn full(capacity: u32, per_second: f64) -> Self {
+        Self {
+            capacity: capacity as f64,
+            per_second,
+            tokens: capacity as f64,
+            last: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.last = now;
+    }
+
+    /// How long until a token is available, zero if one is available right now.
+    pub fn wait_time(&mut self, now: Instant) -> Duration {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            // Only too long for a `Duration` with an absurdly slow refill rate.
+            Duration::try_from_secs_f64((1.0 - self.tokens) / self.per_second)
+                .unwrap_or(Duration::MAX)
+        }
+    }
+
+    fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+}
+
+/// What to do with a call that goes over a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleMode {
+    /// Sleep until the call is allowed.
+    Block,
+    /// Fail the call with a [`ThrottledError`].
+    Error,
+}
+
+/// The error (wrapped in an [`io::Error`] of kind [`io::ErrorKind::WouldBlock`]) a call fails
+/// with when it is throttled in [`ThrottleMode::Error`].
+#[derive(Debug, Clone)]
+pub struct ThrottledError {
+    pub device: RPCDevice,
+    pub method: String,
+    /// How long until the call would be allowed.
+    pub retry_after: Duration,
+}
+
+impl Display for ThrottledError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "call to {} on {} throttled, retry after {:?}",
+            self.method, self.device, self.retry_after
+        )
+    }
+}
+
+impl Error for ThrottledError {}
+
+impl ThrottledError {
+    /// Gets the throttle error back out of an error returned by the bus, if it is one.
+    pub fn from_io(error: &io::Error) -> Option<&ThrottledError> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+/// Client side rate limits for calls on an [`RPCBus`](crate::device_bus::RPCBus), to keep tight
+/// loops from lagging the server.
+///
+/// Every limit is optional, a call has to get past all limits that apply to it:
+/// - one bucket shared by all calls on the bus,
+/// - one bucket per device,
+/// - one bucket per method name (kept separately for every device),
+/// - a minimum delay between two calls of the same method on the same device.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    mode: ThrottleMode,
+    global: Option<TokenBucket>,
+    device_limit: Option<(u32, f64)>,
+    devices: HashMap<RPCDevice, TokenBucket>,
+    method_limits: HashMap<String, (u32, f64)>,
+    methods: HashMap<RPCDevice, HashMap<String, TokenBucket>>,
+    cooldowns: HashMap<String, Duration>,
+    last_calls: HashMap<RPCDevice, HashMap<String, Instant>>,
+}
+
+impl Throttle {
+    /// A throttle without any limits.
+    pub fn new(mode: ThrottleMode) -> Self {
+        Self {
+            mode,
+            global: None,
+            device_limit: None,
+            devices: HashMap::new(),
+            method_limits: HashMap::new(),
+            methods: HashMap::new(),
+            cooldowns: HashMap::new(),
+            last_calls: HashMap::new(),
+        }
+    }
+
+    /// Fails on an invalid limit, see [`TokenBucket::new`]. The same goes for
+    /// [`Self::per_device`] and [`Self::per_method`].
+    pub fn global(mut self, capacity: u32, per_second: f64) -> io::Result<Self> {
+        self.global = Some(TokenBucket::new(capacity, per_second)?);
+        Ok(self)
+    }
+
+    pub fn per_device(mut self, capacity: u32, per_second: f64) -> io::Result<Self> {
+        check_limit(capacity, per_second)?;
+        self.device_limit = Some((capacity, per_second));
+        self.devices.clear();
+        Ok(self)
+    }
+
+    pub fn per_method(mut self, method: &str, capacity: u32, per_second: f64) -> io::Result<Self> {
+        check_limit(capacity, per_second)?;
+        self.method_limits
+            .insert(method.to_string(), (capacity, per_second));
+        for methods in self.methods.values_mut() {
+            methods.remove(method);
+        }
+        Ok(self)
+    }
+
+    pub fn cooldown(mut self, method: &str, cooldown: Duration) -> Self {
+        self.cooldowns.insert(method.to_string(), cooldown);
+        self
+    }
+
+    /// Shorthand for a cooldown on `playSound`.
+    pub fn sound_cooldown(self, cooldown: Duration) -> Self {
+        self.cooldown("playSound", cooldown)
+    }
+
+    pub fn mode(&self) -> ThrottleMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: ThrottleMode) {
+        self.mode = mode;
+    }
+
+    /// How long `method` on `device` has to wait, zero if it may go right now.
+    fn wait_time(&mut self, device: RPCDevice, method: &str, now: Instant) -> Duration {
+        let mut wait = Duration::ZERO;
+
+        if let Some(global) = &mut self.global {
+            wait = wait.max(global.wait_time(now));
+        }
+        if let Some((capacity, per_second)) = self.device_limit {
+            let bucket = self
+                .devices
+                .entry(device)
+                .or_insert_with(|| TokenBucket::full(capacity, per_second));
+            wait = wait.max(bucket.wait_time(now));
+        }
+        if let Some((capacity, per_second)) = self.method_limits.get(method) {
+            let methods = self.methods.entry(device).or_default();
+            if !methods.contains_key(method) {
+                methods.insert(
+                    method.to_string(),
+                    TokenBucket::full(*capacity, *per_second),
+                );
+            }
+            if let Some(bucket) = methods.get_mut(method) {
+                wait = wait.max(bucket.wait_time(now));
+            }
+        }
+        if let Some(cooldown) = self.cooldowns.get(method) {
+            if let Some(last) = self.last_calls.get(&device).and_then(|m| m.get(method)) {
+                let ready = *last + *cooldown;
+                wait = wait.max(ready.saturating_duration_since(now));
+            }
+        }
+
+        wait
+    }
+
+    /// Takes a token from every bucket that applies, only call this after [`Self::wait_time`]
+    /// returned zero.
+    fn take(&mut self, device: RPCDevice, method: &str, now: Instant) {
+        if let Some(global) = &mut self.global {
+            global.take();
+        }
+        if let Some(bucket) = self.devices.get_mut(&device) {
+            bucket.take();
+        }
+        if let Some(bucket) = self
+            .methods
+            .get_mut(&device)
+            .and_then(|m| m.get_mut(method))
+        {
+            bucket.take();
+        }
+        if self.cooldowns.contains_key(method) {
+            let last_calls = self.last_calls.entry(device).or_default();
+            if let Some(last) = last_calls.get_mut(method) {
+                *last = now;
+            } else {
+                last_calls.insert(method.to_string(), now);
+            }
+        }
+    }
+
+    /// Lets a call through, blocking or failing if it goes over a limit depending on the mode.
+    pub fn acquire(&mut self, device: RPCDevice, method: &str) -> io::Result<()> {
+        loop {
+            let now = Instant::now();
+            let wait = self.wait_time(device, method, now);
+            if wait.is_zero() {
+                self.take(device, method, now);
+                return Ok(());
+            }
+            match self.mode {
+                ThrottleMode::Block => thread::sleep(wait),
+                ThrottleMode::Error => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        ThrottledError {
+                            device,
+                            method: method.to_string(),
+                            retry_after: wait,
+                        },
+                    ))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_limits() {
+        for (capacity, per_second) in [
+            (0, 1.0),
+            (1, 0.0),
+            (1, -1.0),
+            (1, f64::NAN),
+            (1, f64::INFINITY),
+        ] {
+            let kind = |r: io::Result<Throttle>| r.err().map(|e| e.kind());
+            let throttle = || Throttle::new(ThrottleMode::Error);
+            assert_eq!(
+                TokenBucket::new(capacity, per_second)
+                    .err()
+                    .map(|e| e.kind()),
+                Some(io::ErrorKind::InvalidInput)
+            );
+            assert_eq!(
+                kind(throttle().global(capacity, per_second)),
+                Some(io::ErrorKind::InvalidInput)
+            );
+            assert_eq!(
+                kind(throttle().per_device(capacity, per_second)),
+                Some(io::ErrorKind::InvalidInput)
+            );
+            assert_eq!(
+                kind(throttle().per_method("m", capacity, per_second)),
+                Some(io::ErrorKind::InvalidInput)
+            );
+        }
+    }
+
+    #[test]
+    fn bucket_refills() {
+        let mut bucket = TokenBucket::new(2, 10.0).unwrap();
+        let now = bucket.last;
+        assert_eq!(bucket.wait_time(now), Duration::ZERO);
+        bucket.take();
+        bucket.take();
+        assert_eq!(bucket.wait_time(now), Duration::from_millis(100));
+        assert_eq!(
+            bucket.wait_time(now + Duration::from_millis(100)),
+            Duration::ZERO
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Component, Path};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use tar::{Archive, Builder, EntryType};
+
+use crate::rpc_device::file_import_export_card::ImportSession;
+use crate::rpc_device::FileImportExportCard;
+use crate::transfer::{ExportWriter, ImportReader};
+
+/// The two bytes every gzip stream starts with.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+}
+
+impl Compression {
+    /// The file extension an archive with this compression usually has.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "tar",
+            Compression::Gzip => "tar.gz",
+        }
+    }
+}
+
+/// Packs everything below `dir` into a tar archive written to `out`, paths in the archive are
+/// relative to `dir`. Returns `out` once the archive is complete.
+pub fn pack_dir<W: Write>(dir: &Path, out: W, compression: Compression) -> io::Result<W> {
+    match compression {
+        Compression::None => {
+            let mut builder = Builder::new(out);
+            builder.append_dir_all(".", dir)?;
+            builder.into_inner()
+        }
+        Compression::Gzip => {
+            let mut builder = Builder::new(GzEncoder::new(out, flate2::Compression::default()));
+            builder.append_dir_all(".", dir)?;
+            builder.into_inner()?.finish()
+        }
+    }
+}
+
+/// Unpacks the tar archive read from `input` into `dest`, gzip compression is detected on its
+/// own.
+///
+/// Entries with absolute paths or `..` components and links are rejected, so an archive can never
+/// write outside of `dest`.
+pub fn unpack<R: Read>(input: R, dest: &Path) -> io::Result<()> {
+    let mut input = BufReader::new(input);
+    if input.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        unpack_tar(GzDecoder::new(input), dest)
+    } else {
+        unpack_tar(input, dest)
+    }
+}
+
+fn unpack_tar<R: Read>(input: R, dest: &Path) -> io::Result<()> {
+    std::fs::create_dir_all(dest)?;
+    let mut archive = Archive::new(input);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        if !is_safe_path(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("refusing to unpack {}", path.display()),
+            ));
+        }
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Directory => (),
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("refusing to unpack {} of type {:?}", path.display(), other),
+                ))
+            }
+        }
+
+        if !entry.unpack_in(dest)? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("refusing to unpack {}", path.display()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `path` stays inside the directory it is unpacked in.
+fn is_safe_path(path: &Path) -> bool {
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// Exports everything below `dir` as a single archive called `name`, returns the number of bytes
+/// sent.
+pub fn export_dir(
+    card: &mut FileImportExportCard,
+    dir: &Path,
+    name: &str,
+    compression: Compression,
+) -> anyhow::Result<u64> {
+    let writer = pack_dir(dir, ExportWriter::begin(card, name)?, compression)?;
+    writer.finish()
+}
+
+/// Like [`unpack`], but reads `input` to its end afterwards. Tar readers stop at the end marker,
+/// this makes sure a card sees the whole file consumed and a hash covers all of it.
+pub fn unpack_all<R: Read>(mut input: R, dest: &Path) -> io::Result<()> {
+    unpack(&mut input, dest)?;
+    io::copy(&mut input, &mut io::sink())?;
+    Ok(())
+}
+
+/// Unpacks the archive the player picked into `dest`.
+pub fn import_dir(session: ImportSession<'_>, dest: &Path) -> anyhow::Result<()> {
+    unpack_all(ImportReader::new(session), dest)?;
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::{Read, Write};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+
+use crate::rpc_device::file_import_export_card::ImportSession;
+use crate::rpc_device::FileImportExportCard;
+use crate::transfer::{ExportWriter, ImportReader};
+
+/// Appended to the name of a file to get the name of its manifest.
+pub const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+/// The size and SHA-256 of everything that went through a transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Digest {
+    pub bytes: u64,
+    pub sha256: [u8; 32],
+}
+
+impl Digest {
+    /// The SHA-256 as lowercase hex, like `sha256sum` prints it.
+    pub fn hex(&self) -> String {
+        self.sha256.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl Display for Digest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "sha256 {} ({} bytes)", self.hex(), self.bytes)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Hasher {
+    sha256: Sha256,
+    bytes: u64,
+}
+
+impl Hasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.sha256.update(data);
+        self.bytes += data.len() as u64;
+    }
+
+    /// The digest of everything seen so far, the hasher can keep going afterwards.
+    pub fn digest(&self) -> Digest {
+        Digest {
+            bytes: self.bytes,
+            sha256: self.sha256.clone().finalize().into(),
+        }
+    }
+}
+
+/// Hashes everything read through it, wrap an [`ImportReader`] in it to check an import.
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Hasher,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: Hasher::new(),
+        }
+    }
+
+    pub fn digest(&self) -> Digest {
+        self.hasher.digest()
+    }
+
+    pub fn into_inner(self) -> (R, Digest) {
+        let digest = self.digest();
+        (self.inner, digest)
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+}
+
+/// Hashes everything written through it, wrap an [`ExportWriter`] in it to check an export.
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl<W: Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Hasher::new(),
+        }
+    }
+
+    pub fn digest(&self) -> Digest {
+        self.hasher.digest()
+    }
+
+    pub fn into_inner(self) -> (W, Digest) {
+        let digest = self.digest();
+        (self.inner, digest)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only hash what the inner writer actually took.
+        let len = self.inner.write(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A sidecar file describing a transferred file, so the receiving side can check it arrived
+/// intact.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl Manifest {
+    pub fn new(name: &str, digest: &Digest) -> Self {
+        Self {
+            name: name.to_string(),
+            size: digest.bytes,
+            sha256: digest.hex(),
+        }
+    }
+
+    /// The name the manifest itself is transferred under.
+    pub fn file_name(&self) -> String {
+        format!("{}{}", self.name, MANIFEST_SUFFIX)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    pub fn from_json(data: &[u8]) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    /// Fails if `digest` does not match the manifest.
+    pub fn verify(&self, digest: &Digest) -> anyhow::Result<()> {
+        if self.size != digest.bytes {
+            return Err(anyhow!(
+                "{}: expected {} bytes, got {}",
+                self.name,
+                self.size,
+                digest.bytes
+            ));
+        }
+        if !self.sha256.eq_ignore_ascii_case(&digest.hex()) {
+            return Err(anyhow!(
+                "{}: expected sha256 {}, got {}",
+                self.name,
+                self.sha256,
+                digest.hex()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Exports `manifest` as a file of its own, see [`Manifest::file_name`].
+pub fn export_manifest(card: &mut FileImportExportCard, manifest: &Manifest) -> anyhow::Result<()> {
+    let mut writer = ExportWriter::begin(card, &manifest.file_name())?;
+    writer.write_all(&manifest.to_json()?)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Reads the manifest the player picked.
+pub fn import_manifest(session: ImportSession<'_>) -> anyhow::Result<Manifest> {
+    let mut data = Vec::new();
+    ImportReader::new(session).read_to_end(&mut data)?;
+    Manifest::from_json(&data)
+}
@@ -0,0 +1,169 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+
+use anyhow::anyhow;
+
+/// What to do when the file an import should be written to already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Fail the import.
+    Refuse,
+    /// Write to `name (1).ext`, `name (2).ext`, ... instead.
+    Rename,
+    /// Replace the existing file once the import is complete.
+    Replace,
+}
+
+impl std::str::FromStr for OverwritePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "refuse" => Ok(OverwritePolicy::Refuse),
+            "rename" => Ok(OverwritePolicy::Rename),
+            "replace" => Ok(OverwritePolicy::Replace),
+            _ => Err(anyhow!("unknown overwrite policy {}", s)),
+        }
+    }
+}
+
+/// Checks a file name that came from the player (like [`ImportedFileInfo::name`]) before it is
+/// used as a path. It has to be a single plain file name, anything with a directory in it
+/// (absolute, `..` or just `a/b`) is rejected.
+///
+/// [`ImportedFileInfo::name`]: crate::util::ImportedFileInfo::name
+pub fn safe_file_name(name: &str) -> anyhow::Result<&Path> {
+    let path = Path::new(name);
+    if name.is_empty() || path.file_name().is_none() {
+        return Err(anyhow!("invalid file name {:?}", name));
+    }
+    let single = path.components().count() == 1
+        && matches!(path.components().next(), Some(Component::Normal(_)));
+    if !single || name.chars().any(std::path::is_separator) {
+        return Err(anyhow!("refusing unsafe file name {:?}", name));
+    }
+    Ok(path)
+}
+
+/// Where a file meant for `path` ends up under `policy`.
+pub fn resolve_destination(path: &Path, policy: OverwritePolicy) -> anyhow::Result<PathBuf> {
+    if !path.exists() {
+        return Ok(path.to_path_buf());
+    }
+    match policy {
+        OverwritePolicy::Refuse => Err(anyhow!("{} already exists", path.display())),
+        OverwritePolicy::Replace => Ok(path.to_path_buf()),
+        OverwritePolicy::Rename => {
+            let stem = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let ext = path
+                .extension()
+                .map(|e| format!(".{}", e.to_string_lossy()))
+                .unwrap_or_default();
+            (1..)
+                .map(|i| path.with_file_name(format!("{} ({}){}", stem, i, ext)))
+                .find(|p| !p.exists())
+                .ok_or_else(|| anyhow!("no free name for {}", path.display()))
+        }
+    }
+}
+
+/// A file that is written to a temporary file next to its destination and only renamed into
+/// place by [`AtomicFile::commit`], so an interrupted import never leaves half a file behind.
+///
+/// Dropping it without committing removes the temporary file.
+pub struct AtomicFile {
+    file: Option<File>,
+    tmp: PathBuf,
+    dest: PathBuf,
+    policy: OverwritePolicy,
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path, policy: OverwritePolicy) -> anyhow::Result<Self> {
+        let dest = resolve_destination(path, policy)?;
+        let name = dest
+            .file_name()
+            .ok_or_else(|| anyhow!("invalid destination {}", dest.display()))?;
+        let tmp = dest.with_file_name(format!(
+            ".{}.{}.part",
+            name.to_string_lossy(),
+            process::id()
+        ));
+        let file = OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+        Ok(Self {
+            file: Some(file),
+            tmp,
+            dest,
+            policy,
+        })
+    }
+
+    /// Where the file will be once committed.
+    pub fn path(&self) -> &Path {
+        &self.dest
+    }
+
+    /// Syncs the file and renames it into place, returns where it ended up.
+    pub fn commit(mut self) -> anyhow::Result<PathBuf> {
+        if let Some(file) = self.file.take() {
+            file.sync_all()?;
+        }
+        // Someone else may have created the destination while we were writing.
+        if self.policy != OverwritePolicy::Replace && self.dest.exists() {
+            self.dest = resolve_destination(&self.dest, self.policy)?;
+        }
+        fs::rename(&self.tmp, &self.dest)?;
+        Ok(self.dest.clone())
+    }
+
+    fn file(&mut self) -> io::Result<&mut File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| io::Error::other("file already committed"))
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file()?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file()?.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        // The temporary file is already gone after a successful commit.
+        let _ = fs::remove_file(&self.tmp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_file_names() {
+        for name in ["file.txt", "no_extension", ".hidden", "with space.tar.gz"] {
+            assert_eq!(safe_file_name(name).unwrap(), Path::new(name));
+        }
+    }
+
+    #[test]
+    fn unsafe_file_names() {
+        for name in [
+            "", ".", "..", "/abs", "/", "a/b", "../a", "./a", "a/", "a/../b",
+        ] {
+            assert!(safe_file_name(name).is_err(), "{:?} was accepted", name);
+        }
+    }
+}
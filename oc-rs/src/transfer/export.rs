@@ -0,0 +1,164 @@
+use std::io;
+use std::io::Write;
+
+use crate::rpc_device::file_import_export_card::ExportSession;
+use crate::rpc_device::FileImportExportCard;
+
+/// How many bytes go into a single `writeExportFile` call by default, small enough that a chunk
+/// encoded as json stays a few KiB.
+pub const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+/// Called with the number of bytes sent so far and the expected total, if known.
+pub type ProgressCallback<'a> = Box<dyn FnMut(u64, Option<u64>) + 'a>;
+
+/// Streams a file out through an [`ExportSession`], splitting everything written to it into
+/// `writeExportFile` calls of at most `chunk_size` bytes.
+///
+/// Call [`ExportWriter::finish`] once everything is written, a writer dropped without finishing
+/// (because of an error or otherwise) resets the card so the export is cancelled.
+pub struct ExportWriter<'a> {
+    session: ExportSession<'a>,
+    buf: Vec<u8>,
+    chunk_size: usize,
+    written: u64,
+    total: Option<u64>,
+    progress: Option<ProgressCallback<'a>>,
+    /// A chunk may have reached the card even though its write failed, so nothing is sent after
+    /// a failure, sending it again could duplicate it.
+    failed: bool,
+}
+
+impl<'a> ExportWriter<'a> {
+    pub fn new(session: ExportSession<'a>) -> Self {
+        Self {
+            session,
+            buf: Vec::with_capacity(DEFAULT_CHUNK_SIZE),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            written: 0,
+            total: None,
+            progress: None,
+            failed: false,
+        }
+    }
+
+    /// Starts exporting a file called `name`.
+    pub fn begin(card: &'a mut FileImportExportCard, name: &str) -> anyhow::Result<Self> {
+        Ok(Self::new(card.begin_export(name)?))
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// The expected size of the file, only used for progress reporting.
+    pub fn with_total(mut self, total: u64) -> Self {
+        self.total = Some(total);
+        self
+    }
+
+    /// Calls `progress` with the number of bytes sent so far and the expected total (if set)
+    /// after every chunk.
+    pub fn on_progress(mut self, progress: impl FnMut(u64, Option<u64>) + 'a) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// The number of bytes sent to the card so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    fn check(&self) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::other(
+                "an earlier write to the card failed, the export is incomplete",
+            ));
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, len: usize) -> io::Result<()> {
+        if let Err(e) = self.session.write(&self.buf[..len]) {
+            self.failed = true;
+            return Err(io::Error::other(e));
+        }
+        self.buf.drain(..len);
+        self.written += len as u64;
+        if let Some(progress) = &mut self.progress {
+            progress(self.written, self.total);
+        }
+        Ok(())
+    }
+
+    /// Sends whatever is still buffered and tells the card the file is complete, returns the
+    /// number of bytes exported. Fails if any chunk could not be sent, the export is incomplete
+    /// then.
+    pub fn finish(mut self) -> anyhow::Result<u64> {
+        self.flush()?;
+        self.session.finish()?;
+        Ok(self.written)
+    }
+}
+
+impl Write for ExportWriter<'_> {
+    /// Fails for good once a chunk could not be sent, see [`ExportWriter::finish`].
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check()?;
+        let len = buf.len().min(self.chunk_size - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        if self.buf.len() >= self.chunk_size {
+            self.send(self.chunk_size)?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.check()?;
+        if !self.buf.is_empty() {
+            self.send(self.buf.len())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::device_bus::tests::{bus, send, sent};
+
+    use super::*;
+
+    const OK: &str = r#"{"type":"result","data":null}"#;
+
+    #[test]
+    fn failed_write_is_not_retried() {
+        let (bus, mut other) = bus();
+        let mut card = FileImportExportCard {
+            device: Default::default(),
+            bus: Mutex::new(bus),
+        };
+        send(&mut other, OK);
+        send(&mut other, r#"{"type":"error","data":"disk full"}"#);
+        // Answers everything after that, so only the writer's own checks fail.
+        for _ in 0..4 {
+            send(&mut other, OK);
+        }
+
+        let mut writer = ExportWriter::begin(&mut card, "file")
+            .unwrap()
+            .with_chunk_size(4);
+        assert!(writer.write(b"abcd").is_err());
+        assert!(writer.write(b"efgh").is_err());
+        assert!(writer.write_all(b"efgh").is_err());
+        assert!(writer.flush().is_err());
+        assert!(writer.finish().is_err());
+
+        let sent = sent(&mut other);
+        assert_eq!(sent.matches("writeExportFile").count(), 1);
+        assert!(!sent.contains("finishExportFile"));
+        // Dropping the unfinished session resets the card.
+        assert!(sent.contains("reset"));
+    }
+}
@@ -0,0 +1,53 @@
+use std::io;
+use std::io::Read;
+
+use crate::rpc_device::file_import_export_card::ImportSession;
+
+/// Streams the file of an [`ImportSession`], calling `readImportFile` whenever more data is
+/// needed until the card reports the end of the file.
+pub struct ImportReader<'a> {
+    session: ImportSession<'a>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl<'a> ImportReader<'a> {
+    pub fn new(session: ImportSession<'a>) -> Self {
+        Self {
+            session,
+            chunk: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    pub fn session(&self) -> &ImportSession<'a> {
+        &self.session
+    }
+
+    /// Whether the card has reported the end of the file.
+    pub fn is_eof(&self) -> bool {
+        self.session.is_eof() && self.pos >= self.chunk.len()
+    }
+}
+
+impl Read for ImportReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.pos >= self.chunk.len() {
+            match self.session.read_chunk().map_err(io::Error::other)? {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
@@ -0,0 +1,11 @@
+#[cfg(feature = "archive")]
+pub mod archive;
+#[cfg(feature = "checksum")]
+pub mod checksum;
+pub mod destination;
+pub mod export;
+pub mod import;
+
+pub use destination::{AtomicFile, OverwritePolicy};
+pub use export::ExportWriter;
+pub use import::ImportReader;
@@ -0,0 +1,332 @@
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Formatter;
+
+#[cfg(feature = "nbt")]
+pub mod nbt;
+pub mod resource_location;
+#[cfg(feature = "sides")]
+pub mod side;
+
+pub use resource_location::ResourceLocation;
+#[cfg(feature = "sides")]
+pub use side::{RelativeSide, Side};
+
+#[cfg(feature = "item")]
+use nbt::Nbt;
+
+/// An item stack as OC2 reports it, an empty slot is reported as `minecraft:air`.
+#[cfg(feature = "item")]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Item {
+    id: ResourceLocation,
+    #[serde(rename = "Count")]
+    count: usize,
+    #[serde(
+        default,
+        deserialize_with = "nbt::deserialize_tag",
+        skip_serializing_if = "Option::is_none"
+    )]
+    tag: Option<Nbt>,
+}
+
+/// An enchantment on an item or enchanted book.
+#[cfg(feature = "item")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Enchantment {
+    pub id: ResourceLocation,
+    pub level: i64,
+}
+
+#[cfg(feature = "item")]
+impl Item {
+    pub fn new(id: ResourceLocation, count: usize) -> Self {
+        Self {
+            id,
+            count,
+            tag: None,
+        }
+    }
+
+    pub fn with_tag(mut self, tag: Nbt) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    /// The registry name of the item, like `minecraft:stone`.
+    pub fn id(&self) -> &ResourceLocation {
+        &self.id
+    }
+
+    /// How many items are in the stack.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Whether this is an empty slot, either air or a stack of nothing.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0 || self.id == ResourceLocation::default()
+    }
+
+    /// The NBT data of the stack, only present for items that carry extra data like damage,
+    /// enchantments or a custom name.
+    pub fn tag(&self) -> Option<&Nbt> {
+        self.tag.as_ref()
+    }
+
+    /// How much durability the item has lost.
+    pub fn damage(&self) -> Option<i64> {
+        self.tag.as_ref()?.get("Damage")?.as_i64()
+    }
+
+    /// The custom name as plain text, without any formatting.
+    pub fn display_name(&self) -> Option<String> {
+        let name = self
+            .tag
+            .as_ref()?
+            .get_path(&["display", "Name"])?
+            .as_str()?;
+        Some(text_component_to_plain(name))
+    }
+
+    /// The enchantments on the item, or the stored ones if this is an enchanted book.
+    pub fn enchantments(&self) -> Vec<Enchantment> {
+        let tag = match &self.tag {
+            Some(t) => t,
+            None => return Vec::new(),
+        };
+        let list = tag
+            .get("Enchantments")
+            .or_else(|| tag.get("StoredEnchantments"))
+            .and_then(Nbt::as_list)
+            .unwrap_or_default();
+        list.iter()
+            .filter_map(|e| {
+                Some(Enchantment {
+                    id: e.get("id")?.as_str()?.parse().ok()?,
+                    level: e.get("lvl")?.as_i64()?,
+                })
+            })
+            .collect()
+    }
+
+    /// The lore lines as plain text.
+    pub fn lore(&self) -> Vec<String> {
+        self.tag
+            .as_ref()
+            .and_then(|t| t.get_path(&["display", "Lore"]))
+            .and_then(Nbt::as_list)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(Nbt::as_str)
+            .map(text_component_to_plain)
+            .collect()
+    }
+}
+
+/// Names and lore are stored as json text components, this pulls out the plain text and falls
+/// back to the raw string if it isn't one.
+#[cfg(feature = "item")]
+fn text_component_to_plain(raw: &str) -> String {
+    fn plain(value: &serde_json::Value, out: &mut String) {
+        match value {
+            serde_json::Value::String(s) => out.push_str(s),
+            serde_json::Value::Array(a) => a.iter().for_each(|v| plain(v, out)),
+            serde_json::Value::Object(o) => {
+                if let Some(text) = o.get("text").or_else(|| o.get("translate")) {
+                    plain(text, out);
+                }
+                if let Some(extra) = o.get("extra") {
+                    plain(extra, out);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    match serde_json::from_str(raw) {
+        Ok(value) => {
+            let mut out = String::new();
+            plain(&value, &mut out);
+            out
+        }
+        Err(_) => raw.to_string(),
+    }
+}
+
+/// The file the player picked for an import.
+#[cfg(feature = "file_transfer")]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ImportedFileInfo {
+    name: String,
+    size: usize,
+}
+
+#[cfg(feature = "file_transfer")]
+impl ImportedFileInfo {
+    pub fn get_name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// The name of the file on the player's side, this is not sanitized in any way.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The size of the file in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// Serializes a byte slice straight into the frame as a json array of numbers, without building
+/// a `serde_json::Value` per byte.
+pub struct ByteArray<'a>(pub &'a [u8]);
+
+impl Serialize for ByteArray<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+/// Deserializes a json array of bytes directly into a `Vec<u8>`.
+///
+/// The other side is written in java, which has signed bytes, so both `-128..=-1` and
+/// `128..=255` are accepted for the upper half.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ByteBuf(pub Vec<u8>);
+
+impl ByteBuf {
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+struct ByteBufVisitor;
+
+impl ByteBufVisitor {
+    fn byte<E: Error>(v: i64) -> Result<u8, E> {
+        match v {
+            0..=255 => Ok(v as u8),
+            -128..=-1 => Ok(v as i8 as u8),
+            _ => Err(E::custom(format!("{} is not a byte", v))),
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for ByteBufVisitor {
+    type Value = ByteBuf;
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "an array of bytes")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ByteBuf, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(v) = seq.next_element::<i64>()? {
+            bytes.push(Self::byte(v)?);
+        }
+        Ok(ByteBuf(bytes))
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(v))
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(ByteBufVisitor)
+    }
+}
+
+#[cfg(all(test, feature = "item"))]
+mod tests {
+    use super::*;
+
+    fn item(tag: &str) -> Item {
+        Item::new("minecraft:diamond_sword".parse().unwrap(), 1).with_tag(Nbt::parse(tag).unwrap())
+    }
+
+    fn enchantment(id: &str, level: i64) -> Enchantment {
+        Enchantment {
+            id: id.parse().unwrap(),
+            level,
+        }
+    }
+
+    #[test]
+    fn getters() {
+        let sword = item(
+            r#"{
+                Damage: 12,
+                display: {
+                    Name: '{"text":"Blade","italic":false}',
+                    Lore: ['"first"', '{"text":"sec","extra":[{"text":"ond"}]}']
+                },
+                Enchantments: [{id: "minecraft:sharpness", lvl: 3s}, {id: "unbreaking", lvl: 1s}]
+            }"#,
+        );
+        assert_eq!(sword.damage(), Some(12));
+        assert_eq!(sword.display_name().as_deref(), Some("Blade"));
+        assert_eq!(sword.lore(), ["first", "second"]);
+        assert_eq!(
+            sword.enchantments(),
+            [
+                enchantment("minecraft:sharpness", 3),
+                enchantment("minecraft:unbreaking", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn getters_without_data() {
+        let plain = Item::new("minecraft:stone".parse().unwrap(), 64);
+        assert_eq!(plain.damage(), None);
+        assert_eq!(plain.display_name(), None);
+        assert!(plain.lore().is_empty());
+        assert!(plain.enchantments().is_empty());
+
+        let other = item("{Damage: 1b, display: {Lore: 'not a list'}, Enchantments: [{lvl: 1}]}");
+        assert_eq!(other.damage(), Some(1));
+        assert_eq!(other.display_name(), None);
+        assert!(other.lore().is_empty());
+        assert!(other.enchantments().is_empty());
+    }
+
+    #[test]
+    fn stored_enchantments() {
+        let book = item(r#"{StoredEnchantments: [{id: "minecraft:mending", lvl: 1s}]}"#);
+        assert_eq!(book.enchantments(), [enchantment("minecraft:mending", 1)]);
+    }
+
+    #[test]
+    fn item_with_snbt_tag() {
+        let item: Item = serde_json::from_str(
+            r#"{"id": "minecraft:bow", "Count": 1, "tag": "{Damage: 4, display: {Name: '\"Bow\"'}}"}"#,
+        )
+        .unwrap();
+        assert_eq!(item.damage(), Some(4));
+        assert_eq!(item.display_name().as_deref(), Some("Bow"));
+    }
+
+    #[test]
+    fn text_components() {
+        assert_eq!(text_component_to_plain(r#""plain""#), "plain");
+        assert_eq!(
+            text_component_to_plain(r#"{"text":"a","extra":["b","c"]}"#),
+            "abc"
+        );
+        assert_eq!(text_component_to_plain(r#"[{"text":"a"},"b"]"#), "ab");
+        assert_eq!(
+            text_component_to_plain(r#"{"translate":"item.minecraft.stone"}"#),
+            "item.minecraft.stone"
+        );
+        assert_eq!(text_component_to_plain("not json"), "not json");
+        assert_eq!(text_component_to_plain("{\"text\": 5}"), "");
+    }
+}
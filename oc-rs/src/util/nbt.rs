@@ -0,0 +1,798 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter, Write};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+/// A single NBT tag, the format minecraft stores item data (and everything else) in.
+///
+/// OC2 hands NBT over as plain json, which loses the exact number types, so values read from json
+/// use [`Nbt::Int`]/[`Nbt::Long`] for integers and [`Nbt::Double`] for everything else. SNBT (the
+/// text form used in commands) keeps the exact types, see [`Nbt::parse`] and the `Display` impl.
+#[derive(Debug, Clone)]
+pub enum Nbt {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Nbt>),
+    Compound(BTreeMap<String, Nbt>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Nbt {
+    /// Parses SNBT, like `{display:{Name:'{"text":"Sword"}'},Damage:3}`.
+    pub fn parse(snbt: &str) -> Result<Nbt, SnbtError> {
+        let mut parser = Parser { src: snbt, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_ws();
+        if parser.pos != snbt.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    /// Looks up `key` if this is a compound.
+    pub fn get(&self, key: &str) -> Option<&Nbt> {
+        self.as_compound()?.get(key)
+    }
+
+    /// Follows a path of compound keys, `tag.get_path(&["display", "Name"])`.
+    pub fn get_path(&self, path: &[&str]) -> Option<&Nbt> {
+        path.iter().try_fold(self, |nbt, key| nbt.get(key))
+    }
+
+    /// Any integer type widened to `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Nbt::Byte(v) => Some(*v as i64),
+            Nbt::Short(v) => Some(*v as i64),
+            Nbt::Int(v) => Some(*v as i64),
+            Nbt::Long(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Any number type widened to `f64`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Nbt::Float(v) => Some(*v as f64),
+            Nbt::Double(v) => Some(*v),
+            _ => self.as_i64().map(|v| v as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Nbt::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Nbt]> {
+        match self {
+            Nbt::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&BTreeMap<String, Nbt>> {
+        match self {
+            Nbt::Compound(c) => Some(c),
+            _ => None,
+        }
+    }
+
+    /// The name of the tag type, for error messages.
+    fn type_name(&self) -> &'static str {
+        match self {
+            Nbt::Byte(_) => "byte",
+            Nbt::Short(_) => "short",
+            Nbt::Int(_) => "int",
+            Nbt::Long(_) => "long",
+            Nbt::Float(_) => "float",
+            Nbt::Double(_) => "double",
+            Nbt::ByteArray(_) => "byte array",
+            Nbt::String(_) => "string",
+            Nbt::List(_) => "list",
+            Nbt::Compound(_) => "compound",
+            Nbt::IntArray(_) => "int array",
+            Nbt::LongArray(_) => "long array",
+        }
+    }
+
+    /// Converts the json form OC2 sends, see the type docs for how numbers are mapped.
+    pub fn from_json(value: &Value) -> Nbt {
+        match value {
+            Value::Null => Nbt::Compound(BTreeMap::new()),
+            Value::Bool(b) => Nbt::Byte(*b as i8),
+            Value::Number(n) => match n.as_i64() {
+                Some(v) => match i32::try_from(v) {
+                    Ok(v) => Nbt::Int(v),
+                    Err(_) => Nbt::Long(v),
+                },
+                None => Nbt::Double(n.as_f64().unwrap_or_default()),
+            },
+            Value::String(s) => Nbt::String(s.clone()),
+            Value::Array(a) => Nbt::List(a.iter().map(Nbt::from_json).collect()),
+            Value::Object(o) => Nbt::Compound(
+                o.iter()
+                    .map(|(k, v)| (k.clone(), Nbt::from_json(v)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+// Floats are compared by their bits, so `Nbt` can be `Eq` and `Hash` and a tag always equals
+// itself, even with a NaN in it.
+impl PartialEq for Nbt {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Nbt::Byte(a), Nbt::Byte(b)) => a == b,
+            (Nbt::Short(a), Nbt::Short(b)) => a == b,
+            (Nbt::Int(a), Nbt::Int(b)) => a == b,
+            (Nbt::Long(a), Nbt::Long(b)) => a == b,
+            (Nbt::Float(a), Nbt::Float(b)) => a.to_bits() == b.to_bits(),
+            (Nbt::Double(a), Nbt::Double(b)) => a.to_bits() == b.to_bits(),
+            (Nbt::ByteArray(a), Nbt::ByteArray(b)) => a == b,
+            (Nbt::String(a), Nbt::String(b)) => a == b,
+            (Nbt::List(a), Nbt::List(b)) => a == b,
+            (Nbt::Compound(a), Nbt::Compound(b)) => a == b,
+            (Nbt::IntArray(a), Nbt::IntArray(b)) => a == b,
+            (Nbt::LongArray(a), Nbt::LongArray(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Nbt {}
+
+impl Hash for Nbt {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Nbt::Byte(v) => v.hash(state),
+            Nbt::Short(v) => v.hash(state),
+            Nbt::Int(v) => v.hash(state),
+            Nbt::Long(v) => v.hash(state),
+            Nbt::Float(v) => v.to_bits().hash(state),
+            Nbt::Double(v) => v.to_bits().hash(state),
+            Nbt::ByteArray(v) => v.hash(state),
+            Nbt::String(v) => v.hash(state),
+            Nbt::List(v) => v.hash(state),
+            Nbt::Compound(v) => v.hash(state),
+            Nbt::IntArray(v) => v.hash(state),
+            Nbt::LongArray(v) => v.hash(state),
+        }
+    }
+}
+
+impl FromStr for Nbt {
+    type Err = SnbtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Nbt::parse(s)
+    }
+}
+
+/// Serializes to the same json form OC2 uses.
+impl Serialize for Nbt {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Nbt::Byte(v) => serializer.serialize_i8(*v),
+            Nbt::Short(v) => serializer.serialize_i16(*v),
+            Nbt::Int(v) => serializer.serialize_i32(*v),
+            Nbt::Long(v) => serializer.serialize_i64(*v),
+            Nbt::Float(v) => serializer.serialize_f32(*v),
+            Nbt::Double(v) => serializer.serialize_f64(*v),
+            Nbt::ByteArray(v) => v.serialize(serializer),
+            Nbt::String(v) => serializer.serialize_str(v),
+            Nbt::List(v) => {
+                let mut seq = serializer.serialize_seq(Some(v.len()))?;
+                for e in v {
+                    seq.serialize_element(e)?;
+                }
+                seq.end()
+            }
+            Nbt::Compound(v) => {
+                let mut map = serializer.serialize_map(Some(v.len()))?;
+                for (k, e) in v {
+                    map.serialize_entry(k, e)?;
+                }
+                map.end()
+            }
+            Nbt::IntArray(v) => v.serialize(serializer),
+            Nbt::LongArray(v) => v.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Nbt {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Nbt::from_json(&Value::deserialize(deserializer)?))
+    }
+}
+
+/// Deserializes a tag sent either as json or as an SNBT string.
+pub(crate) fn deserialize_tag<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Nbt>, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::Null => Ok(None),
+        Value::String(s) => Nbt::parse(&s).map(Some).map_err(serde::de::Error::custom),
+        v => Ok(Some(Nbt::from_json(&v))),
+    }
+}
+
+/// Prints SNBT that [`Nbt::parse`] (and minecraft) reads back to the same value.
+impl Display for Nbt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Nbt::Byte(v) => write!(f, "{}b", v),
+            Nbt::Short(v) => write!(f, "{}s", v),
+            Nbt::Int(v) => write!(f, "{}", v),
+            Nbt::Long(v) => write!(f, "{}L", v),
+            Nbt::Float(v) => write!(f, "{}f", float_str(*v as f64, format!("{:?}", v))),
+            Nbt::Double(v) => write!(f, "{}d", float_str(*v, format!("{:?}", v))),
+            Nbt::ByteArray(v) => write_array(f, "B", v.iter().map(|e| format!("{}b", e))),
+            Nbt::String(v) => write_quoted(f, v),
+            Nbt::List(v) => {
+                f.write_char('[')?;
+                for (i, e) in v.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                f.write_char(']')
+            }
+            Nbt::Compound(v) => {
+                f.write_char('{')?;
+                for (i, (k, e)) in v.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    if !k.is_empty() && k.chars().all(is_unquoted_char) {
+                        f.write_str(k)?;
+                    } else {
+                        write_quoted(f, k)?;
+                    }
+                    write!(f, ":{}", e)?;
+                }
+                f.write_char('}')
+            }
+            Nbt::IntArray(v) => write_array(f, "I", v.iter().map(|e| e.to_string())),
+            Nbt::LongArray(v) => write_array(f, "L", v.iter().map(|e| format!("{}L", e))),
+        }
+    }
+}
+
+/// Makes sure a float has a `.` so minecraft reads it back as a float, `{:?}` prints large values
+/// as `1e20`.
+fn float_str(v: f64, debug: String) -> String {
+    if !v.is_finite() || debug.contains('.') {
+        debug
+    } else if let Some(e) = debug.find('e') {
+        format!("{}.0{}", &debug[..e], &debug[e..])
+    } else {
+        format!("{}.0", debug)
+    }
+}
+
+fn write_array(
+    f: &mut Formatter<'_>,
+    prefix: &str,
+    elements: impl Iterator<Item = String>,
+) -> std::fmt::Result {
+    write!(f, "[{};", prefix)?;
+    for (i, e) in elements.enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        f.write_str(&e)?;
+    }
+    f.write_char(']')
+}
+
+fn write_quoted(f: &mut Formatter<'_>, s: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            f.write_char('\\')?;
+        }
+        f.write_char(c)?;
+    }
+    f.write_char('"')
+}
+
+fn is_unquoted_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+')
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnbtError {
+    /// Byte offset in the input where parsing failed.
+    pub pos: usize,
+    pub message: String,
+}
+
+impl Display for SnbtError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid snbt at {}: {}", self.pos, self.message)
+    }
+}
+
+impl Error for SnbtError {}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> SnbtError {
+        SnbtError {
+            pos: self.pos,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SnbtError> {
+        self.skip_ws();
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    /// Consumes a `,` if there is one, returns whether it did.
+    fn comma(&mut self) -> bool {
+        self.skip_ws();
+        if self.peek() == Some(',') {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Result<Nbt, SnbtError> {
+        self.skip_ws();
+        match self.peek() {
+            Some('{') => self.compound(),
+            Some('[') => self.list(),
+            Some('"') | Some('\'') => Ok(Nbt::String(self.quoted()?)),
+            Some(_) => {
+                let start = self.pos;
+                let word = self.unquoted();
+                if word.is_empty() {
+                    self.pos = start;
+                    return Err(self.error("expected a value"));
+                }
+                Ok(parse_word(word))
+            }
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn compound(&mut self) -> Result<Nbt, SnbtError> {
+        self.expect('{')?;
+        let mut map = BTreeMap::new();
+        self.skip_ws();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Nbt::Compound(map));
+        }
+        loop {
+            self.skip_ws();
+            let key = match self.peek() {
+                Some('"') | Some('\'') => self.quoted()?,
+                _ => {
+                    let key = self.unquoted();
+                    if key.is_empty() {
+                        return Err(self.error("expected a key"));
+                    }
+                    key.to_string()
+                }
+            };
+            self.expect(':')?;
+            map.insert(key, self.value()?);
+            if !self.comma() {
+                break;
+            }
+        }
+        self.expect('}')?;
+        Ok(Nbt::Compound(map))
+    }
+
+    fn list(&mut self) -> Result<Nbt, SnbtError> {
+        self.expect('[')?;
+        // Typed arrays start with `B;`, `I;` or `L;`.
+        let start = self.pos;
+        self.skip_ws();
+        if let Some(t @ ('B' | 'I' | 'L')) = self.peek() {
+            self.pos += 1;
+            self.skip_ws();
+            if self.peek() == Some(';') {
+                self.pos += 1;
+                return self.array(t);
+            }
+        }
+        self.pos = start;
+
+        let mut list = Vec::new();
+        self.skip_ws();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Nbt::List(list));
+        }
+        loop {
+            self.skip_ws();
+            let start = self.pos;
+            let value = self.value()?;
+            // Every element of a list has the same type.
+            if let Some(first) = list.first() {
+                if std::mem::discriminant(first) != std::mem::discriminant(&value) {
+                    self.pos = start;
+                    return Err(self.error(&format!(
+                        "can't mix {} and {} in a list",
+                        first.type_name(),
+                        value.type_name()
+                    )));
+                }
+            }
+            list.push(value);
+            if !self.comma() {
+                break;
+            }
+        }
+        self.expect(']')?;
+        Ok(Nbt::List(list))
+    }
+
+    /// The elements have to be of the array's type, `[B;1b]`, `[I;1]` and `[L;1L]`.
+    fn array(&mut self, t: char) -> Result<Nbt, SnbtError> {
+        let name = match t {
+            'B' => "a byte",
+            'I' => "an int",
+            _ => "a long",
+        };
+        let mut values = Vec::new();
+        self.skip_ws();
+        if self.peek() != Some(']') {
+            loop {
+                self.skip_ws();
+                let start = self.pos;
+                let value = parse_word(self.unquoted());
+                let matches = matches!(
+                    (t, &value),
+                    ('B', Nbt::Byte(_)) | ('I', Nbt::Int(_)) | ('L', Nbt::Long(_))
+                );
+                if !matches {
+                    self.pos = start;
+                    return Err(self.error(&format!(
+                        "expected {}, got {}",
+                        name,
+                        value.type_name()
+                    )));
+                }
+                values.push(value);
+                if !self.comma() {
+                    break;
+                }
+            }
+        }
+        self.expect(']')?;
+        // Every value was type checked above.
+        let values = values.iter().filter_map(Nbt::as_i64);
+        Ok(match t {
+            'B' => Nbt::ByteArray(values.map(|v| v as i8).collect()),
+            'I' => Nbt::IntArray(values.map(|v| v as i32).collect()),
+            _ => Nbt::LongArray(values.collect()),
+        })
+    }
+
+    /// Only the quote itself and `\\` can be escaped, like in minecraft.
+    fn quoted(&mut self) -> Result<String, SnbtError> {
+        let quote = self.peek().unwrap();
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') => {
+                    let start = self.pos;
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(c) if c == quote || c == '\\' => {
+                            s.push(c);
+                            self.pos += 1;
+                        }
+                        Some(c) => {
+                            self.pos = start;
+                            return Err(self.error(&format!("invalid escape \\{}", c)));
+                        }
+                        None => return Err(self.error("unterminated string")),
+                    }
+                }
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.pos += c.len_utf8();
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn unquoted(&mut self) -> &str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !is_unquoted_char(c) {
+                break;
+            }
+            self.pos += 1;
+        }
+        &self.src[start..self.pos]
+    }
+}
+
+/// Turns an unquoted word into a number or boolean if it looks like one, a string otherwise.
+///
+/// Follows minecraft's rules: the suffix is case insensitive, integers can't have leading zeros,
+/// a double without a `d` needs a `.` (`1e5` is a string) and a number that is out of range for
+/// its type is a string too.
+fn parse_word(word: &str) -> Nbt {
+    match word {
+        "true" => return Nbt::Byte(1),
+        "false" => return Nbt::Byte(0),
+        _ => (),
+    }
+
+    let (body, suffix) = match word.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() && i > 0 => (&word[..i], Some(c)),
+        _ => (word, None),
+    };
+    let parsed = match suffix.map(|c| c.to_ascii_lowercase()) {
+        Some('b') if is_integer(body) => body.parse().ok().map(Nbt::Byte),
+        Some('s') if is_integer(body) => body.parse().ok().map(Nbt::Short),
+        Some('l') if is_integer(body) => body.parse().ok().map(Nbt::Long),
+        Some('f') if is_float(body) => body.parse().ok().map(Nbt::Float),
+        Some('d') if is_float(body) => body.parse().ok().map(Nbt::Double),
+        None if is_integer(body) => body.parse().ok().map(Nbt::Int),
+        None if is_float(body) && body.contains('.') => body.parse().ok().map(Nbt::Double),
+        _ => None,
+    };
+    parsed.unwrap_or_else(|| Nbt::String(word.to_string()))
+}
+
+fn strip_sign(s: &str) -> &str {
+    s.strip_prefix(['-', '+']).unwrap_or(s)
+}
+
+fn is_digits(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// `[-+]?(0|[1-9][0-9]*)`
+fn is_integer(s: &str) -> bool {
+    let digits = strip_sign(s);
+    digits == "0" || (digits.starts_with(|c: char| matches!(c, '1'..='9')) && is_digits(digits))
+}
+
+/// `[-+]?([0-9]+[.]?|[0-9]*[.][0-9]+)(e[-+]?[0-9]+)?`, case insensitive.
+fn is_float(s: &str) -> bool {
+    let (mantissa, exponent) = match strip_sign(s).split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(strip_sign(e))),
+        None => (strip_sign(s), None),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    is_digits(int)
+        && is_digits(frac)
+        && !(int.is_empty() && frac.is_empty())
+        && exponent.is_none_or(|e| !e.is_empty() && is_digits(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(snbt: &str) -> Nbt {
+        Nbt::parse(snbt).unwrap_or_else(|e| panic!("{:?}: {}", snbt, e))
+    }
+
+    fn round_trip(nbt: Nbt) {
+        let snbt = nbt.to_string();
+        assert_eq!(parse(&snbt), nbt, "{}", snbt);
+    }
+
+    fn compound<const N: usize>(entries: [(&str, Nbt); N]) -> Nbt {
+        Nbt::Compound(entries.map(|(k, v)| (k.to_string(), v)).into())
+    }
+
+    #[test]
+    fn round_trip_every_tag() {
+        for nbt in [
+            Nbt::Byte(-128),
+            Nbt::Short(i16::MAX),
+            Nbt::Int(-7),
+            Nbt::Long(i64::MIN),
+            Nbt::Float(1.5),
+            Nbt::Float(1e20),
+            Nbt::Double(-0.1),
+            Nbt::Double(3.0),
+            Nbt::Double(1e-300),
+            Nbt::ByteArray(vec![-1, 0, 127]),
+            Nbt::ByteArray(vec![]),
+            Nbt::IntArray(vec![i32::MIN, 5]),
+            Nbt::LongArray(vec![i64::MAX]),
+            Nbt::String(String::new()),
+            Nbt::String("say \"hi\" \\ 'there'".to_string()),
+            Nbt::String("123".to_string()),
+            Nbt::List(vec![]),
+            Nbt::List(vec![Nbt::Int(1), Nbt::Int(2)]),
+            Nbt::List(vec![Nbt::List(vec![Nbt::String("a".to_string())])]),
+            compound([]),
+            compound([
+                ("Damage", Nbt::Int(3)),
+                ("with space", Nbt::Byte(1)),
+                ("", Nbt::Long(2)),
+                (
+                    "display",
+                    compound([("Name", Nbt::String("{\"text\":\"x\"}".to_string()))]),
+                ),
+            ]),
+        ] {
+            round_trip(nbt);
+        }
+    }
+
+    #[test]
+    fn number_suffixes() {
+        assert_eq!(parse("1b"), Nbt::Byte(1));
+        assert_eq!(parse("-1B"), Nbt::Byte(-1));
+        assert_eq!(parse("2s"), Nbt::Short(2));
+        assert_eq!(parse("3"), Nbt::Int(3));
+        assert_eq!(parse("+3"), Nbt::Int(3));
+        assert_eq!(parse("4L"), Nbt::Long(4));
+        assert_eq!(parse("5.5f"), Nbt::Float(5.5));
+        assert_eq!(parse("1e5f"), Nbt::Float(1e5));
+        assert_eq!(parse("6d"), Nbt::Double(6.0));
+        assert_eq!(parse("6.5"), Nbt::Double(6.5));
+        assert_eq!(parse(".5"), Nbt::Double(0.5));
+        assert_eq!(parse("1.5e3"), Nbt::Double(1500.0));
+        assert_eq!(parse("true"), Nbt::Byte(1));
+        assert_eq!(parse("false"), Nbt::Byte(0));
+    }
+
+    #[test]
+    fn words_that_are_not_numbers() {
+        for word in [
+            "1e5", "300b", "007", "1.2.3", "nand", "infd", "-nanf", "abc", "1x", "-",
+        ] {
+            assert_eq!(parse(word), Nbt::String(word.to_string()), "{}", word);
+        }
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(parse("\"a b\""), Nbt::String("a b".to_string()));
+        assert_eq!(
+            parse(r#"'say "hi"'"#),
+            Nbt::String("say \"hi\"".to_string())
+        );
+        assert_eq!(parse(r#""a\"b\\c""#), Nbt::String("a\"b\\c".to_string()));
+        assert_eq!(parse(r"'it\'s'"), Nbt::String("it's".to_string()));
+        assert_eq!(parse("stone_1.x"), Nbt::String("stone_1.x".to_string()));
+    }
+
+    #[test]
+    fn typed_arrays() {
+        assert_eq!(parse("[B;1b,-2b]"), Nbt::ByteArray(vec![1, -2]));
+        assert_eq!(parse("[ B ; 1b , 2b ]"), Nbt::ByteArray(vec![1, 2]));
+        assert_eq!(parse("[I;]"), Nbt::IntArray(vec![]));
+        assert_eq!(parse("[I; 1, 2]"), Nbt::IntArray(vec![1, 2]));
+        assert_eq!(parse("[L;1L,2L]"), Nbt::LongArray(vec![1, 2]));
+        // Lists of lists can hold lists of different types.
+        assert_eq!(
+            parse(r#"[[1],["a"]]"#),
+            Nbt::List(vec![
+                Nbt::List(vec![Nbt::Int(1)]),
+                Nbt::List(vec![Nbt::String("a".to_string())])
+            ])
+        );
+        // Without the `;` it is a plain list of strings.
+        assert_eq!(
+            parse("[B, I]"),
+            Nbt::List(vec![
+                Nbt::String("B".to_string()),
+                Nbt::String("I".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn compounds() {
+        let nbt = parse(r#"{ display : { Name : '{"text":"Sword"}' }, Damage: 3, "a b": [] }"#);
+        assert_eq!(
+            nbt.get_path(&["display", "Name"]).and_then(Nbt::as_str),
+            Some(r#"{"text":"Sword"}"#)
+        );
+        assert_eq!(nbt.get("Damage"), Some(&Nbt::Int(3)));
+        assert_eq!(nbt.get("a b"), Some(&Nbt::List(vec![])));
+    }
+
+    fn error(snbt: &str) -> SnbtError {
+        match Nbt::parse(snbt) {
+            Ok(v) => panic!("{:?} parsed as {:?}", snbt, v),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("[B;300]").pos, 3);
+        assert_eq!(error("[B;1b, 128]").message, "expected a byte, got int");
+        assert_eq!(error("[B;128b]").message, "expected a byte, got string");
+        assert_eq!(error("[B;1,2]").message, "expected a byte, got int");
+        assert_eq!(error("[I;1b]").message, "expected an int, got byte");
+        assert_eq!(
+            error("[I;2147483648L]").message,
+            "expected an int, got long"
+        );
+        assert_eq!(error("[I;1.5]").message, "expected an int, got double");
+        assert_eq!(error("[L;1]").message, "expected a long, got int");
+        assert_eq!(error("[L;abc]").message, "expected a long, got string");
+        assert_eq!(
+            error("[1b,\"a\"]").message,
+            "can't mix byte and string in a list"
+        );
+        assert_eq!(error("[1b, \"a\"]").pos, 5);
+        assert_eq!(error("[1,2L]").message, "can't mix int and long in a list");
+        assert_eq!(
+            error("[[],{}]").message,
+            "can't mix list and compound in a list"
+        );
+        assert_eq!(error(r#""a\nb""#).message, "invalid escape \\n");
+        assert_eq!(error(r#""a\nb""#).pos, 2);
+        assert_eq!(error(r#""it\'s""#).message, "invalid escape \\'");
+        assert_eq!(error(r#"'say \"hi\"'"#).message, "invalid escape \\\"");
+        assert_eq!(error("\"open").message, "unterminated string");
+        assert_eq!(error("'open\\").message, "unterminated string");
+        assert_eq!(error("{a:1").message, "expected '}'");
+        assert_eq!(error("{a 1}").message, "expected ':'");
+        assert_eq!(error("{:1}").message, "expected a key");
+        assert_eq!(error("[1,]").message, "expected a value");
+        assert_eq!(error("[1 2]").message, "expected ']'");
+        assert_eq!(error("").message, "unexpected end of input");
+        assert_eq!(error("1 2").message, "trailing characters");
+        assert_eq!(error("minecraft:stone").message, "trailing characters");
+    }
+}
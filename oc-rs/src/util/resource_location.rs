@@ -0,0 +1,196 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A minecraft registry name like `minecraft:stone`, used for item ids, sounds and anything else
+/// that lives in a registry.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResourceLocation {
+    namespace: String,
+    path: String,
+}
+
+pub const DEFAULT_NAMESPACE: &str = "minecraft";
+
+impl ResourceLocation {
+    pub fn new(namespace: &str, path: &str) -> Result<Self, InvalidResourceLocation> {
+        let invalid = || InvalidResourceLocation(format!("{}:{}", namespace, path));
+        if !namespace.chars().all(is_namespace_char) || path.is_empty() {
+            return Err(invalid());
+        }
+        if !path.chars().all(|c| is_namespace_char(c) || c == '/') {
+            return Err(invalid());
+        }
+        Ok(Self {
+            namespace: if namespace.is_empty() {
+                DEFAULT_NAMESPACE
+            } else {
+                namespace
+            }
+            .to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    /// Parses `namespace:path`, a missing namespace means `minecraft`.
+    pub fn parse(s: &str) -> Result<Self, InvalidResourceLocation> {
+        match s.split_once(':') {
+            Some((namespace, path)) => Self::new(namespace, path),
+            None => Self::new(DEFAULT_NAMESPACE, s),
+        }
+    }
+
+    /// `minecraft:{path}`.
+    pub fn minecraft(path: &str) -> Result<Self, InvalidResourceLocation> {
+        Self::new(DEFAULT_NAMESPACE, path)
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+fn is_namespace_char(c: char) -> bool {
+    matches!(c, 'a'..='z' | '0'..='9' | '_' | '-' | '.')
+}
+
+/// `minecraft:air`, what an empty slot reports as its item.
+impl Default for ResourceLocation {
+    fn default() -> Self {
+        Self {
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            path: "air".to_string(),
+        }
+    }
+}
+
+impl Display for ResourceLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.path)
+    }
+}
+
+impl FromStr for ResourceLocation {
+    type Err = InvalidResourceLocation;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<&str> for ResourceLocation {
+    type Error = InvalidResourceLocation;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::parse(s)
+    }
+}
+
+impl Serialize for ResourceLocation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ResourceLocation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Only `a-z 0-9 _ - .` are allowed in the namespace, the path may also contain `/`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidResourceLocation(pub String);
+
+impl Display for InvalidResourceLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid resource location {:?}", self.0)
+    }
+}
+
+impl Error for InvalidResourceLocation {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> ResourceLocation {
+        ResourceLocation::parse(s).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    #[test]
+    fn valid() {
+        let location = parse("mymod:blocks/copper_wire.v2");
+        assert_eq!(location.namespace(), "mymod");
+        assert_eq!(location.path(), "blocks/copper_wire.v2");
+        assert_eq!(location.to_string(), "mymod:blocks/copper_wire.v2");
+        assert_eq!(
+            parse("my-mod_2:a-b"),
+            ResourceLocation::new("my-mod_2", "a-b").unwrap()
+        );
+        assert_eq!(
+            "block.note_block.harp".parse::<ResourceLocation>().unwrap(),
+            ResourceLocation::minecraft("block.note_block.harp").unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_namespace() {
+        assert_eq!(
+            parse("stone"),
+            ResourceLocation::minecraft("stone").unwrap()
+        );
+        assert_eq!(
+            parse(":stone"),
+            ResourceLocation::minecraft("stone").unwrap()
+        );
+        assert_eq!(parse("stone").to_string(), "minecraft:stone");
+        assert_eq!(ResourceLocation::default(), parse("minecraft:air"));
+    }
+
+    #[test]
+    fn invalid() {
+        for s in [
+            "",
+            "minecraft:",
+            "Minecraft:stone",
+            "minecraft:Stone",
+            "my mod:stone",
+            "my/mod:stone",
+            "a:b:c",
+            "minecraft:stone!",
+        ] {
+            assert!(ResourceLocation::parse(s).is_err(), "{}", s);
+        }
+        assert!(ResourceLocation::new("minecraft", "").is_err());
+        assert!(ResourceLocation::minecraft("a b").is_err());
+        assert_eq!(
+            ResourceLocation::parse("a:B").unwrap_err().to_string(),
+            r#"invalid resource location "a:B""#
+        );
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let location = parse("oc2:hard_drive");
+        let json = serde_json::to_string(&location).unwrap();
+        assert_eq!(json, r#""oc2:hard_drive""#);
+        assert_eq!(
+            serde_json::from_str::<ResourceLocation>(&json).unwrap(),
+            location
+        );
+        assert_eq!(
+            serde_json::from_str::<ResourceLocation>(r#""dirt""#).unwrap(),
+            ResourceLocation::minecraft("dirt").unwrap()
+        );
+        assert!(serde_json::from_str::<ResourceLocation>(r#""Dirt""#).is_err());
+        assert!(serde_json::from_str::<ResourceLocation>("5").is_err());
+    }
+}
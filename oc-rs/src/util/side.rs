@@ -0,0 +1,272 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// One of the six sides of a block, as the redstone interface and other devices name them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Side {
+    Up = 0,
+    Down = 1,
+    North = 2,
+    East = 3,
+    South = 4,
+    West = 5,
+}
+
+impl Side {
+    pub const ALL: [Side; 6] = [
+        Side::Up,
+        Side::Down,
+        Side::North,
+        Side::East,
+        Side::South,
+        Side::West,
+    ];
+
+    pub fn all() -> impl Iterator<Item = Side> {
+        Self::ALL.into_iter()
+    }
+
+    /// The name the devices expect, like `north`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Side::Up => "up",
+            Side::Down => "down",
+            Side::North => "north",
+            Side::East => "east",
+            Side::South => "south",
+            Side::West => "west",
+        }
+    }
+
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::Up => Side::Down,
+            Side::Down => Side::Up,
+            Side::North => Side::South,
+            Side::East => Side::West,
+            Side::South => Side::North,
+            Side::West => Side::East,
+        }
+    }
+
+    pub fn is_horizontal(&self) -> bool {
+        !matches!(self, Side::Up | Side::Down)
+    }
+
+    /// Rotates a horizontal side clockwise when seen from above, `Up` and `Down` stay as they are.
+    pub fn rotate_clockwise(&self) -> Side {
+        match self {
+            Side::North => Side::East,
+            Side::East => Side::South,
+            Side::South => Side::West,
+            Side::West => Side::North,
+            vertical => *vertical,
+        }
+    }
+
+    pub fn rotate_counter_clockwise(&self) -> Side {
+        match self {
+            Side::North => Side::West,
+            Side::West => Side::South,
+            Side::South => Side::East,
+            Side::East => Side::North,
+            vertical => *vertical,
+        }
+    }
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Side {
+    type Err = UnknownSide;
+
+    /// Case insensitive, also accepts `top` and `bottom`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "up" | "top" => Ok(Side::Up),
+            "down" | "bottom" => Ok(Side::Down),
+            "north" => Ok(Side::North),
+            "east" => Ok(Side::East),
+            "south" => Ok(Side::South),
+            "west" => Ok(Side::West),
+            _ => Err(UnknownSide(s.to_string())),
+        }
+    }
+}
+
+impl Serialize for Side {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Side {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A side relative to the way a block faces, so a program keeps working however the computer is
+/// placed. Resolve it to a [`Side`] with [`RelativeSide::resolve`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RelativeSide {
+    Front,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl RelativeSide {
+    pub const ALL: [RelativeSide; 6] = [
+        RelativeSide::Front,
+        RelativeSide::Back,
+        RelativeSide::Left,
+        RelativeSide::Right,
+        RelativeSide::Up,
+        RelativeSide::Down,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RelativeSide::Front => "front",
+            RelativeSide::Back => "back",
+            RelativeSide::Left => "left",
+            RelativeSide::Right => "right",
+            RelativeSide::Up => "up",
+            RelativeSide::Down => "down",
+        }
+    }
+
+    /// The absolute side for a block whose front is `facing`, left and right are as seen when
+    /// looking out of the front. `None` for left and right if `facing` is up or down, as those
+    /// are ambiguous then.
+    pub fn resolve(&self, facing: Side) -> Option<Side> {
+        match self {
+            RelativeSide::Front => Some(facing),
+            RelativeSide::Back => Some(facing.opposite()),
+            RelativeSide::Up => Some(Side::Up),
+            RelativeSide::Down => Some(Side::Down),
+            RelativeSide::Left if facing.is_horizontal() => Some(facing.rotate_counter_clockwise()),
+            RelativeSide::Right if facing.is_horizontal() => Some(facing.rotate_clockwise()),
+            RelativeSide::Left | RelativeSide::Right => None,
+        }
+    }
+}
+
+impl Display for RelativeSide {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RelativeSide {
+    type Err = UnknownSide;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "front" => Ok(RelativeSide::Front),
+            "back" => Ok(RelativeSide::Back),
+            "left" => Ok(RelativeSide::Left),
+            "right" => Ok(RelativeSide::Right),
+            "up" | "top" => Ok(RelativeSide::Up),
+            "down" | "bottom" => Ok(RelativeSide::Down),
+            _ => Err(UnknownSide(s.to_string())),
+        }
+    }
+}
+
+impl Serialize for RelativeSide {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for RelativeSide {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownSide(pub String);
+
+impl Display for UnknownSide {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown side {:?}", self.0)
+    }
+}
+
+impl Error for UnknownSide {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sides() {
+        for side in Side::all() {
+            assert_eq!(side.name().parse::<Side>(), Ok(side));
+            assert_eq!(side.name().to_uppercase().parse::<Side>(), Ok(side));
+        }
+        assert_eq!("top".parse::<Side>(), Ok(Side::Up));
+        assert_eq!("Bottom".parse::<Side>(), Ok(Side::Down));
+        assert_eq!("NoRtH".parse::<Side>(), Ok(Side::North));
+        assert_eq!("left".parse::<Side>(), Err(UnknownSide("left".to_string())));
+        assert!(" up".parse::<Side>().is_err());
+        assert_eq!("TOP".parse::<RelativeSide>(), Ok(RelativeSide::Up));
+        assert_eq!("Left".parse::<RelativeSide>(), Ok(RelativeSide::Left));
+        assert!("north".parse::<RelativeSide>().is_err());
+    }
+
+    #[test]
+    fn opposite_and_rotation() {
+        for side in Side::all() {
+            assert_ne!(side.opposite(), side);
+            assert_eq!(side.opposite().opposite(), side);
+            assert_eq!(side.rotate_clockwise().rotate_counter_clockwise(), side);
+            assert_eq!(side.is_horizontal(), side.rotate_clockwise() != side);
+        }
+        let mut side = Side::North;
+        for expected in [Side::East, Side::South, Side::West, Side::North] {
+            side = side.rotate_clockwise();
+            assert_eq!(side, expected);
+        }
+        assert_eq!(Side::North.rotate_counter_clockwise(), Side::West);
+        assert_eq!(Side::Up.rotate_clockwise(), Side::Up);
+        assert_eq!(Side::Down.rotate_counter_clockwise(), Side::Down);
+    }
+
+    #[test]
+    fn resolve() {
+        use RelativeSide::*;
+        for (facing, left, right) in [
+            (Side::North, Side::West, Side::East),
+            (Side::East, Side::North, Side::South),
+            (Side::South, Side::East, Side::West),
+            (Side::West, Side::South, Side::North),
+        ] {
+            assert_eq!(Left.resolve(facing), Some(left), "{}", facing);
+            assert_eq!(Right.resolve(facing), Some(right), "{}", facing);
+            assert_eq!(Front.resolve(facing), Some(facing));
+            assert_eq!(Back.resolve(facing), Some(facing.opposite()));
+            assert_eq!(Up.resolve(facing), Some(Side::Up));
+            assert_eq!(Down.resolve(facing), Some(Side::Down));
+        }
+        for facing in [Side::Up, Side::Down] {
+            assert_eq!(Left.resolve(facing), None);
+            assert_eq!(Right.resolve(facing), None);
+            assert_eq!(Front.resolve(facing), Some(facing));
+        }
+    }
+}